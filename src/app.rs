@@ -1,10 +1,9 @@
 use gleam::gl;
-use surfman::GLApi;
-use webrender::{Renderer, RendererOptions, ShaderPrecacheFlags};
+use webrender::{RendererOptions, ShaderPrecacheFlags, DebugFlags};
 use webrender::api::{
     RenderApi, DisplayListBuilder, FontInstanceKey,
-    RenderNotifier, DocumentId, PipelineId, DebugCommand, DebugFlags,
-    ExternalImageHandler, OutputImageHandler, ColorF, Epoch,
+    RenderNotifier, DocumentId, PipelineId, Transaction,
+    ExternalImageHandler, OutputImageHandler, ColorF,
     units::{LayoutRect, LayoutPoint, LayoutSize}
 };
 use winit::{
@@ -12,12 +11,27 @@ use winit::{
     VirtualKeyCode, Event, WindowEvent, ControlFlow,
     dpi::LogicalSize
 };
-use std::{cell::RefCell, rc::Rc, path::PathBuf};
+use std::{
+    cell::RefCell, rc::Rc, path::PathBuf, thread,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH}
+};
 use crate::{
-    window::Window,
-    compositor::Compositor
+    window::{Window, WindowMethods},
+    compositor::{self, Backend, Capture, Compositor, LocalRenderer, RenderedEpoch, ROOT_PIPELINE_ID},
+    error::Error,
+    fonts::FontSource,
+    headless::{FrameNotifier, FrameReady, HeadlessWindow},
+    render_thread::RenderThread,
+    vector_images::{TileSources, VectorImageHandler},
+    watcher::FileWatcher,
+    webrender_surfman::{BuildError, WebrenderSurfman, WebrenderSurfmanBuilder}
 };
 
+/// How long a headless run waits for webrender to produce a frame.
+const FRAME_TIMEOUT: Duration = Duration::from_secs(10);
+/// The time between the frames of a headless run.
+const HEADLESS_FRAME_DURATION: Duration = Duration::from_nanos(1_000_000_000 / 60);
+
 struct Notifier {
     events_proxy: EventsLoopProxy,
 }
@@ -71,6 +85,71 @@ impl HandyDandyRectBuilder for (i32, i32) {
     }
 }
 
+/// Runner settings that are not part of an `App`, usually from the command line.
+#[derive(Clone, Debug)]
+pub struct RunOptions {
+    /// Overrides `App::SIZE`.
+    pub size: Option<(u32, u32)>,
+    /// Overrides `App::TITLE`.
+    pub title: Option<String>,
+    /// Added to the debug flags of the app's `RendererOptions`.
+    pub debug_flags: DebugFlags,
+    /// Device pixel ratio of headless runs, windows use the monitor's.
+    pub device_pixel_ratio: Option<f32>,
+    /// Render this many frames offscreen instead of opening a window.
+    pub headless: Option<usize>,
+    /// Where a headless run writes its last frame.
+    pub output: Option<PathBuf>,
+    pub loop_mode: LoopMode,
+    /// Adapter and GL context of the window or headless surface.
+    pub surfman: WebrenderSurfmanBuilder,
+    /// Render each window on its own thread instead of the event loop's,
+    /// which fails on Windows. Headless runs always render on the calling
+    /// thread.
+    pub threaded: bool,
+}
+
+/// Title and size of a window opened by `App::extra_windows`.
+#[derive(Clone, Debug)]
+pub struct WindowOptions {
+    pub title: String,
+    pub size: (u32, u32),
+}
+
+/// How a windowed run schedules its frames.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LoopMode {
+    /// Block on the event loop, draw in response to events.
+    OnEvent,
+    /// Poll the event loop, tick the app and draw every frame, capped to
+    /// `target_fps` if given.
+    Continuous { target_fps: Option<u32> },
+}
+
+/// Timing passed to `App::tick`.
+#[derive(Clone, Copy, Debug)]
+pub struct FrameTime {
+    /// Time elapsed since the previous tick.
+    pub delta: Duration,
+    pub frame_number: u64,
+}
+
+impl Default for RunOptions {
+    fn default() -> Self {
+        RunOptions {
+            size: None,
+            title: None,
+            debug_flags: DebugFlags::empty(),
+            device_pixel_ratio: None,
+            headless: None,
+            output: None,
+            loop_mode: LoopMode::OnEvent,
+            surfman: WebrenderSurfmanBuilder::default(),
+            threaded: false,
+        }
+    }
+}
+
 pub trait App {
 	const PRECACHE_SHADER_FLAGS: ShaderPrecacheFlags = ShaderPrecacheFlags::EMPTY;
 	const SIZE: (u32, u32) = (800, 600);
@@ -80,7 +159,13 @@ pub trait App {
         Some(ColorF::new(0.3, 0.0, 0.0, 1.0))
     }
 
-    fn add_font(&self) -> Option<(PathBuf, f32)> {
+    /// Options of the renderer. Without a `blob_image_handler` the runner
+    /// installs a `VectorImageHandler`.
+    fn renderer_options(&self) -> Option<RendererOptions> {
+        None
+    }
+
+    fn add_font(&self) -> Option<(FontSource, f32)> {
         None
     }
 
@@ -92,6 +177,56 @@ pub trait App {
         font_instance_key: Option<FontInstanceKey>
     ) -> DisplayListBuilder;
 
+    /// Windows opened next to the main one, e.g. an inspector. Each gets
+    /// its own compositor and document and is referred to by its index in
+    /// this list. Headless runs only render the main window.
+    fn extra_windows(&self) -> Vec<WindowOptions> {
+        Vec::new()
+    }
+
+    /// Sends the display lists of pipelines other than the root that changed,
+    /// with `Compositor::send_display_list`, and returns whether it sent any.
+    /// Called before the root is rebuilt and after events and ticks, so parts
+    /// of the main window embedded as iframes update independently of it.
+    fn update_pipelines(
+        &mut self,
+        _compositor: &mut Compositor,
+        _font_instance_key: Option<FontInstanceKey>
+    ) -> bool {
+        false
+    }
+
+    /// Called once a display list sent for the main window is on screen,
+    /// returns whether the display list needs to be rebuilt, e.g. to start
+    /// the next step of an animation.
+    fn epoch_rendered(&mut self, _rendered: RenderedEpoch) -> bool {
+        false
+    }
+
+    /// Builds the display list of the extra window `index`.
+    fn build_extra_display_list(
+        &mut self,
+        _index: usize,
+        compositor: &mut Compositor,
+        pipeline_id: PipelineId,
+        _document_id: DocumentId,
+        _font_instance_key: Option<FontInstanceKey>
+    ) -> DisplayListBuilder {
+        DisplayListBuilder::new(pipeline_id, compositor.get_layout_size())
+    }
+
+    /// Files the app is built from. A windowed run watches them and calls
+    /// `reload` when one of them changes.
+    fn watched_files(&self) -> Vec<PathBuf> {
+        Vec::new()
+    }
+
+    /// Re-reads the watched files, returns whether the display list and the
+    /// font from `add_font` should be recreated.
+    fn reload(&mut self) -> bool {
+        false
+    }
+
     fn on_event(
         &mut self,
         _: winit::WindowEvent,
@@ -101,130 +236,357 @@ pub trait App {
         false
     }
 
+    /// Like `on_event`, for events of the extra window `index`.
+    fn on_extra_window_event(
+        &mut self,
+        _index: usize,
+        _: winit::WindowEvent,
+        _: &mut RenderApi,
+        _: DocumentId,
+    ) -> bool {
+        false
+    }
+
+    /// Called before every frame in `LoopMode::Continuous`, returns whether
+    /// the display list needs to be rebuilt.
+    fn tick(
+        &mut self,
+        _: FrameTime,
+        _: &mut RenderApi,
+        _: DocumentId,
+    ) -> bool {
+        false
+    }
+
+    /// Called with the GL context webrender renders the main window with,
+    /// e.g. to import the surfaces of a `SurfaceProducer`. Not called when
+    /// rendering on a render thread, the GL context is not current on the
+    /// app's thread then.
     fn get_image_handlers(
         &mut self,
         _gl: &dyn gl::Gl,
+        _webrender_surfman: &WebrenderSurfman,
     ) -> (Option<Box<dyn ExternalImageHandler>>,
           Option<Box<dyn OutputImageHandler>>) {
         (None, None)
     }
+    /// Draws into the main window's framebuffer after webrender. Not called
+    /// when rendering on a render thread.
     fn draw_custom(&mut self, _gl: &dyn gl::Gl) {}
 }
 
-pub fn run<E: App>(
-    app: &mut E,
-    options: Option<RendererOptions>,
-) {
-    env_logger::init();
-
-    #[cfg(target_os = "macos")]
-    {
-        use core_foundation::{self as cf, base::TCFType};
-        let i = cf::bundle::CFBundle::main_bundle().info_dictionary();
-        let mut i = unsafe { i.to_mutable() };
-        i.set(
-            cf::string::CFString::new("NSSupportsAutomaticGraphicsSwitching"),
-            cf::boolean::CFBoolean::true_value().into_CFType(),
-        );
-    }
+/// Runs a default constructed `E`, this is what the example registry points at.
+pub fn run_example<E: App + Default>(options: &RunOptions) -> Result<(), Error> {
+    let mut app = E::default();
+    run(&mut app, options)
+}
 
-    let events_loop = Rc::new(RefCell::new(EventsLoop::new()));
-    let win = Window::new(
-        E::TITLE,
-        LogicalSize::new(E::SIZE.0 as f64, E::SIZE.1 as f64),
-        events_loop.clone()
-    );
+/// Renders a default constructed `E` offscreen, see `run_headless`.
+pub fn render_example<E: App + Default>(options: &RunOptions, frames: usize) -> Result<Capture, Error> {
+    let mut app = E::default();
+    run_headless(&mut app, options, frames)
+}
 
+/// Creates the GL context, the renderer, the document and the compositor
+/// rendering into `window`, the renderer on a render thread if
+/// `threaded` is set. The app's image handlers are only installed for the
+/// main window, `extra_index` is `None`.
+fn create_compositor<E: App>(
+    app: &mut E,
+    window: Rc<dyn WindowMethods>,
+    extra_index: Option<usize>,
+    notifier: Box<dyn RenderNotifier>,
+    options: &RunOptions,
+    threaded: bool,
+) -> Result<Compositor, Error> {
     // Initialize surfman
-    let webrender_surfman = win.webrender_surfman();
-
-    // Get GL bindings
-    let webrender_gl = match webrender_surfman.connection().gl_api() {
-        GLApi::GL => unsafe { gl::GlFns::load_with(|s| webrender_surfman.get_proc_address(s)) },
-        GLApi::GLES => unsafe {
-            gl::GlesFns::load_with(|s| webrender_surfman.get_proc_address(s))
-        },
-    };
-
-    // Make sure the gl context is made current.
-    webrender_surfman.make_gl_context_current().unwrap();
+    let webrender_surfman = window.create_webrender_surfman(&options.surfman).map_err(|err| match err {
+        BuildError::Connection(err) => Error::Connection(err),
+        BuildError::Adapter(err) | BuildError::SurfaceType(err) => Error::Adapter(err),
+        BuildError::Context(err) => Error::Context(err),
+    })?;
 
-    println!("OpenGL version {}", webrender_gl.get_string(gl::VERSION));
-
-    let coordinates = win.get_coordinates();
+    let coordinates = window.get_coordinates()?;
     let device_pixel_ratio = coordinates.hidpi_factor.get();
 
     println!("Device pixel ratio: {}", device_pixel_ratio);
 
-    let notifier = Box::new(Notifier::new(events_loop.borrow().create_proxy()));
-
-    let (mut webrender, sender) = Renderer::new(
-        webrender_gl.clone(),
-        notifier,
-        RendererOptions {
-            device_pixel_ratio,
-            clear_color: app.clear_color(),
-            ..options.unwrap_or_default()
-        },
-        None,
-        coordinates.framebuffer
-    )
-    .expect("Unable to initialize webrender!");
+    let mut renderer_options = app.renderer_options().unwrap_or_default();
+    renderer_options.debug_flags |= options.debug_flags;
+    // Rasterizes the images of `Compositor::add_vector_image` and
+    // `Compositor::add_generated_image`.
+    let tile_sources = TileSources::default();
+    if renderer_options.blob_image_handler.is_none() {
+        renderer_options.blob_image_handler = Some(Box::new(VectorImageHandler::new(tile_sources.clone())));
+    }
+    let renderer_options = RendererOptions {
+        device_pixel_ratio,
+        clear_color: app.clear_color(),
+        ..renderer_options
+    };
+
+    let (backend, sender, new_frames) = if threaded {
+        let (render_thread, sender, new_frames) =
+            RenderThread::spawn(webrender_surfman, notifier, renderer_options, coordinates)?;
+        (Backend::Thread(render_thread), sender, new_frames)
+    } else {
+        let (notifier, new_frames) = compositor::reporting_notifier(notifier);
+        let (mut renderer, sender) =
+            LocalRenderer::new(webrender_surfman, notifier, renderer_options, coordinates.framebuffer)?;
+
+        // set image handler
+        if extra_index.is_none() {
+            let (external, output) = app.get_image_handlers(&*renderer.webrender_gl(), &renderer.webrender_surfman());
+            renderer.set_image_handlers(external, output);
+        }
+        (Backend::Local(renderer), sender, new_frames)
+    };
 
     let webrender_api = sender.create_api();
 
-    // webrender_api.send_debug_cmd(DebugCommand::SetFlags(DebugFlags::PROFILER_DBG));
-
     let document_id = webrender_api.add_document(coordinates.framebuffer, 0);
 
-    // set image handler
-    let (external, output) = app.get_image_handlers(&*webrender_gl);
-    if let Some(output_image_handler) = output {
-        webrender.set_output_image_handler(output_image_handler);
+    Ok(Compositor::new(
+        window,
+        coordinates,
+        backend,
+        document_id,
+        webrender_api,
+        new_frames,
+        tile_sources,
+    ))
+}
+
+#[cfg(target_os = "macos")]
+fn enable_automatic_graphics_switching() {
+    use core_foundation::{self as cf, base::TCFType};
+    let i = cf::bundle::CFBundle::main_bundle().info_dictionary();
+    let mut i = unsafe { i.to_mutable() };
+    i.set(
+        cf::string::CFString::new("NSSupportsAutomaticGraphicsSwitching"),
+        cf::boolean::CFBoolean::true_value().into_CFType(),
+    );
+}
+
+pub fn run<E: App>(
+    app: &mut E,
+    options: &RunOptions,
+) -> Result<(), Error> {
+    #[cfg(target_os = "macos")]
+    enable_automatic_graphics_switching();
+
+    let events_loop = Rc::new(RefCell::new(EventsLoop::new()));
+
+    let main_window = WindowOptions {
+        title: options.title.clone().unwrap_or_else(|| E::TITLE.to_owned()),
+        size: options.size.unwrap_or(E::SIZE),
+    };
+    let extra_windows = app.extra_windows();
+    let mut windows = Vec::with_capacity(1 + extra_windows.len());
+    for (index, window_options) in Some(main_window).into_iter().chain(extra_windows).enumerate() {
+        let (width, height) = window_options.size;
+        let window = Rc::new(Window::new(
+            &window_options.title,
+            LogicalSize::new(width as f64, height as f64),
+            events_loop.clone(),
+        )?);
+
+        let extra_index = index.checked_sub(1);
+        let notifier = Box::new(Notifier::new(events_loop.borrow().create_proxy()));
+        let mut compositor = create_compositor(app, window.clone(), extra_index, notifier, options, options.threaded)?;
+        let font_instance_key = add_app_font(app, &mut compositor)?;
+
+        windows.push(RunnerWindow {
+            window,
+            compositor,
+            extra_index,
+            font_instance_key,
+            screenshot_requested: false,
+        });
     }
-    if let Some(external_image_handler) = external {
-        webrender.set_external_image_handler(external_image_handler);
+
+    let watched_files = app.watched_files();
+    let watcher = if watched_files.is_empty() {
+        None
+    } else {
+        let events_proxy = events_loop.borrow().create_proxy();
+        Some(FileWatcher::new(watched_files, move || {
+            let _ = events_proxy.wakeup();
+        }))
+    };
+
+    let mut runner = Runner {
+        app,
+        windows,
+        watcher,
+    };
+    runner.send_display_lists();
+
+    println!("Entering event loop");
+
+    let result = match options.loop_mode {
+        LoopMode::OnEvent => {
+            let mut result = Ok(());
+            events_loop.borrow_mut().run_forever(|global_event| {
+                let response = runner.handle_event(global_event).and_then(|response| {
+                    match response {
+                        Response::Exit => return Ok(ControlFlow::Break),
+                        Response::Skip => return Ok(ControlFlow::Continue),
+                        // Rebuilding the root updates the other pipelines too.
+                        Response::Rebuild => runner.send_display_lists(),
+                        Response::Redraw => {
+                            runner.update_pipelines();
+                        },
+                    }
+                    runner.render()?;
+                    Ok(ControlFlow::Continue)
+                });
+                response.unwrap_or_else(|err| {
+                    result = Err(err);
+                    ControlFlow::Break
+                })
+            });
+            result
+        }
+        LoopMode::Continuous { target_fps } => {
+            runner.run_continuously(&mut events_loop.borrow_mut(), target_fps)
+        }
+    };
+
+    for window in runner.windows {
+        let (composited, skipped) = window.compositor.frame_counts();
+        println!("Window {:?}: composited {} frames, skipped {}", window.window.id(), composited, skipped);
+        window.compositor.deinit();
     }
+    result
+}
 
-    let epoch = Epoch(0);
-    let pipeline_id = PipelineId(0, 0);
+/// What the event loop should do after an event.
+enum Response {
+    /// Nothing changed, don't draw a frame.
+    Skip,
+    /// Draw the windows that webrender has a new frame for, or that were
+    /// invalidated.
+    Redraw,
+    /// Build and send new display lists, then draw.
+    Rebuild,
+    Exit,
+}
 
-    let mut compositor = Compositor::new(Rc::new(win), webrender, document_id, webrender_api, webrender_surfman, webrender_gl.clone());
+/// The state of a windowed run, shared by the event loop flavours.
+struct Runner<'a, E: App> {
+    app: &'a mut E,
+    /// The main window first, then the open extra windows.
+    windows: Vec<RunnerWindow>,
+    watcher: Option<FileWatcher>,
+}
 
-    let font_instance_key =  app.add_font().map(|font| compositor.set_font_instance(font, document_id));
+/// A window of a run, with the compositor and document drawing into it.
+struct RunnerWindow {
+    window: Rc<Window>,
+    compositor: Compositor,
+    /// Index in `App::extra_windows`, `None` for the main window.
+    extra_index: Option<usize>,
+    font_instance_key: Option<FontInstanceKey>,
+    screenshot_requested: bool,
+}
 
-    let builder = app.build_display_list(
-        &mut compositor,
-        pipeline_id,
-        document_id,
-        font_instance_key
-    );
-    compositor.send_display_list(epoch, pipeline_id, builder);
+impl<'a, E: App> Runner<'a, E> {
+    /// Builds and sends the root display lists of all windows. The main
+    /// window's other pipelines are updated first so that the frame of the
+    /// root includes them.
+    fn send_display_lists(&mut self) {
+        for window in &mut self.windows {
+            let document_id = window.compositor.document_id();
+            match window.extra_index {
+                None => send_display_list(self.app, &mut window.compositor, window.font_instance_key),
+                Some(index) => {
+                    let builder = self.app.build_extra_display_list(
+                        index,
+                        &mut window.compositor,
+                        ROOT_PIPELINE_ID,
+                        document_id,
+                        window.font_instance_key
+                    );
+                    window.compositor.send_display_list(builder);
+                }
+            }
+        }
+    }
 
+    /// Lets the app send display lists for the pipelines of the main window
+    /// that changed, returns whether it did.
+    fn update_pipelines(&mut self) -> bool {
+        let window = &mut self.windows[0];
+        self.app.update_pipelines(&mut window.compositor, window.font_instance_key)
+    }
 
-    println!("Entering event loop");
+    fn handle_event(&mut self, global_event: Event) -> Result<Response, Error> {
+        let (window_id, win_event) = match global_event {
+            Event::WindowEvent { window_id, event } => (window_id, event),
+            Event::Awakened => {
+                // Webrender wakes the loop when it built a frame, `render`
+                // skips the windows that don't need compositing.
+                return Ok(if self.reload_changed_files() { Response::Rebuild } else { Response::Redraw });
+            },
+            _ => return Ok(Response::Skip),
+        };
+        let position = match self.windows.iter().position(|window| window.window.id() == window_id) {
+            Some(position) => position,
+            None => return Ok(Response::Skip),
+        };
 
-    // run event_loop
-    events_loop.borrow_mut().run_forever(|global_event| {
-        let mut custom_event = true;
-        let win_event = match global_event {
-            Event::WindowEvent { event, .. } => event,
-            _ => return ControlFlow::Continue,
+        let extra_index = self.windows[position].extra_index;
+        let close = match win_event {
+            WindowEvent::CloseRequested => true,
+            WindowEvent::KeyboardInput {
+                input: winit::KeyboardInput {
+                    state: winit::ElementState::Pressed,
+                    virtual_keycode: Some(VirtualKeyCode::Escape),
+                    ..
+                },
+                ..
+            } => true,
+            _ => false,
         };
+        if close {
+            if extra_index.is_none() {
+                return Ok(Response::Exit);
+            }
+            // Closing an extra window leaves the others running.
+            self.windows.remove(position).compositor.deinit();
+            return Ok(Response::Skip);
+        }
 
-        match win_event {
-            WindowEvent::CloseRequested => return ControlFlow::Break,
+        let app = &mut *self.app;
+        let window = &mut self.windows[position];
+        let document_id = window.compositor.document_id();
+        let mut on_event = |event: WindowEvent, compositor: &mut Compositor| match extra_index {
+            None => app.on_event(event, compositor.get_webrender_api(), document_id),
+            Some(index) => app.on_extra_window_event(index, event, compositor.get_webrender_api(), document_id),
+        };
+
+        let response = match win_event {
+            WindowEvent::Resized(size) => {
+                window.window.set_inner_size(size);
+                window.compositor.resize()?;
+                window.compositor.invalidate();
+                Response::Rebuild
+            },
+            WindowEvent::HiDpiFactorChanged(_) => {
+                window.compositor.resize()?;
+                window.compositor.invalidate();
+                Response::Rebuild
+            },
+            WindowEvent::Refresh => {
+                window.compositor.invalidate();
+                Response::Redraw
+            },
             | winit::WindowEvent::AxisMotion { .. }
             | winit::WindowEvent::CursorMoved { .. } => {
-                custom_event = app.on_event(
-                        win_event,
-                        compositor.get_webrender_api(),
-                        document_id,
-                    );
+                let custom_event = on_event(win_event, &mut window.compositor);
                 // skip high-frequency events from triggering a frame draw.
-                if !custom_event {
-                    return winit::ControlFlow::Continue;
-                }
+                if custom_event { Response::Rebuild } else { Response::Skip }
             },
             WindowEvent::KeyboardInput {
                 input: winit::KeyboardInput {
@@ -234,32 +596,242 @@ pub fn run<E: App>(
                 },
                 ..
             } => match key {
-                VirtualKeyCode::Escape => return ControlFlow::Break,
-                _ => {},
+                VirtualKeyCode::F12 => {
+                    window.screenshot_requested = true;
+                    window.compositor.invalidate();
+                    Response::Redraw
+                },
+                _ => Response::Rebuild,
             },
-            other => custom_event = app.on_event(
-                other,
-                compositor.get_webrender_api(),
-                document_id
-            )
+            other => {
+                let custom_event = on_event(other, &mut window.compositor);
+                if custom_event { Response::Rebuild } else { Response::Redraw }
+            }
+        };
+        Ok(response)
+    }
+
+    /// Reloads the app if the watcher saw one of its files change, and
+    /// recreates its font. Returns whether the display lists need a rebuild.
+    fn reload_changed_files(&mut self) -> bool {
+        let watcher = match self.watcher {
+            Some(ref watcher) if watcher.take_changed() => watcher,
+            _ => return false,
+        };
+
+        println!("Reloading");
+        if !self.app.reload() {
+            return false;
+        }
+        watcher.set_paths(self.app.watched_files());
+
+        for window in &mut self.windows {
+            window.compositor.clear_fonts();
+            window.font_instance_key = match add_app_font(self.app, &mut window.compositor) {
+                Ok(font_instance_key) => font_instance_key,
+                Err(err) => {
+                    println!("{}", err);
+                    None
+                }
+            };
+        }
+        true
+    }
+
+    /// Draws and presents the windows that need it, then tells the app
+    /// which display lists of the main window made it to the screen.
+    fn render(&mut self) -> Result<(), Error> {
+        let mut rebuild = false;
+        for window in &mut self.windows {
+            if !window.compositor.needs_composite() {
+                window.compositor.skip_frame();
+                continue;
+            }
+            window.compositor.composite()?;
+            if window.extra_index.is_none() {
+                if let Some(gl) = window.compositor.webrender_gl() {
+                    self.app.draw_custom(&*gl);
+                }
+            }
+            if window.screenshot_requested {
+                window.screenshot_requested = false;
+                let path = screenshot_path();
+                match window.compositor.screenshot(&path) {
+                    Ok(()) => println!("Saved screenshot to {:?}", path),
+                    Err(err) => println!("Failed to save screenshot: {:?}", err),
+                }
+            }
+            window.compositor.present()?;
+            if window.extra_index.is_none() {
+                for rendered in window.compositor.take_rendered_epochs() {
+                    rebuild |= self.app.epoch_rendered(rendered);
+                }
+            }
+        }
+        if rebuild {
+            self.send_display_lists();
         }
+        Ok(())
+    }
 
-        if custom_event {
-            let builder = app.build_display_list(
-                &mut compositor,
-                pipeline_id,
-                document_id,
-                font_instance_key
-            );
-            compositor.send_display_list(epoch, pipeline_id, builder);
+    /// Polls the events and draws a frame per iteration, ticking the app.
+    /// Without a target frame rate the loop is paced by `present`.
+    fn run_continuously(
+        &mut self,
+        events_loop: &mut EventsLoop,
+        target_fps: Option<u32>,
+    ) -> Result<(), Error> {
+        let frame_budget = target_fps.map(|fps| Duration::from_secs(1) / fps.max(1));
+        let mut last_frame = Instant::now();
+        let mut frame_number = 0;
+
+        loop {
+            let frame_start = Instant::now();
+            let mut exit = false;
+            let mut rebuild = false;
+            let mut result = Ok(());
+
+            events_loop.poll_events(|global_event| match self.handle_event(global_event) {
+                Ok(Response::Exit) => exit = true,
+                Ok(Response::Rebuild) => rebuild = true,
+                Ok(Response::Redraw) | Ok(Response::Skip) => {},
+                Err(err) => result = Err(err),
+            });
+            result?;
+            if exit {
+                return Ok(());
+            }
+
+            let frame_time = FrameTime {
+                delta: frame_start - last_frame,
+                frame_number,
+            };
+            last_frame = frame_start;
+            let compositor = &mut self.windows[0].compositor;
+            let document_id = compositor.document_id();
+            rebuild |= self.app.tick(frame_time, compositor.get_webrender_api(), document_id);
+
+            if rebuild {
+                self.send_display_lists();
+            } else {
+                self.update_pipelines();
+            }
+            self.render()?;
+            frame_number += 1;
+
+            if let Some(frame_budget) = frame_budget {
+                let elapsed = frame_start.elapsed();
+                if elapsed < frame_budget {
+                    thread::sleep(frame_budget - elapsed);
+                }
+            }
         }
+    }
+}
 
-        compositor.composite();
-        app.draw_custom(&*webrender_gl.clone());
-        compositor.present();
+/// Builds and sends the root display list of the main window, after the
+/// app updated its other pipelines so that the frame of the root includes them.
+fn send_display_list<E: App>(app: &mut E, compositor: &mut Compositor, font_instance_key: Option<FontInstanceKey>) {
+    let document_id = compositor.document_id();
+    let builder = app.build_display_list(compositor, ROOT_PIPELINE_ID, document_id, font_instance_key);
+    app.update_pipelines(compositor, font_instance_key);
+    compositor.send_display_list(builder);
+}
 
-        ControlFlow::Continue
-    });
+/// Creates the instance of the font the app asks for in `add_font`.
+fn add_app_font<E: App>(app: &E, compositor: &mut Compositor) -> Result<Option<FontInstanceKey>, Error> {
+    match app.add_font() {
+        Some((source, size)) => Ok(Some(compositor.get_font_instance(&source, size, &[])?)),
+        None => Ok(None),
+    }
+}
+
+fn screenshot_path() -> PathBuf {
+    let millis = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|since_epoch| since_epoch.as_millis())
+        .unwrap_or(0);
+    PathBuf::from(format!("screenshot-{}.png", millis))
+}
 
+/// Drives `app` without a window or an event loop: renders `frames` frames
+/// into a generic surfman surface and returns the pixels of the last one.
+/// The app is ticked before every frame but the first, as if frames were
+/// `HEADLESS_FRAME_DURATION` apart.
+pub fn run_headless<E: App>(
+    app: &mut E,
+    options: &RunOptions,
+    frames: usize,
+) -> Result<Capture, Error> {
+    let window = Rc::new(HeadlessWindow::new(
+        options.size.unwrap_or(E::SIZE),
+        options.device_pixel_ratio.unwrap_or(1.0),
+    ));
+
+    let (notifier, frame_ready) = FrameNotifier::new();
+
+    let mut compositor = create_compositor(app, window, None, Box::new(notifier), options, false)?;
+    let result = render_headless_frames(app, &mut compositor, &frame_ready, frames.max(1));
     compositor.deinit();
+    result
+}
+
+fn render_headless_frames<E: App>(
+    app: &mut E,
+    compositor: &mut Compositor,
+    frame_ready: &FrameReady,
+    frames: usize,
+) -> Result<Capture, Error> {
+    let webrender_gl = compositor.webrender_gl().expect("Headless runs render on this thread");
+    let font_instance_key = add_app_font(app, compositor)?;
+    let document_id = compositor.document_id();
+
+    let mut rebuild = false;
+    let mut frame = 0;
+    loop {
+        let mut frames_before = frame_ready.count();
+        if frame == 0 {
+            send_display_list(app, compositor, font_instance_key);
+        } else {
+            let frame_time = FrameTime {
+                delta: HEADLESS_FRAME_DURATION,
+                frame_number: frame as u64,
+            };
+            rebuild |= app.tick(frame_time, compositor.get_webrender_api(), document_id);
+            if rebuild {
+                send_display_list(app, compositor, font_instance_key);
+            } else {
+                app.update_pipelines(compositor, font_instance_key);
+            }
+            // A frame to wait for even when the app sent nothing. Webrender
+            // handles the transactions of a document in order, so it comes
+            // after those of the tick.
+            let mut txn = Transaction::new();
+            txn.generate_frame();
+            compositor.get_webrender_api().send_transaction(document_id, txn);
+        }
+
+        // The first frame can be one built for another pipeline, wait until
+        // the root's display list made it into a composited frame.
+        loop {
+            if !frame_ready.wait_past(frames_before, FRAME_TIMEOUT) {
+                return Err(Error::FrameTimeout(FRAME_TIMEOUT));
+            }
+            frames_before = frame_ready.count();
+            compositor.composite()?;
+            if compositor.is_rendered(ROOT_PIPELINE_ID) {
+                break;
+            }
+        }
+        app.draw_custom(&*webrender_gl);
+        frame += 1;
+        if frame == frames {
+            return compositor.read_pixels();
+        }
+        compositor.present()?;
+        rebuild = false;
+        for rendered in compositor.take_rendered_epochs() {
+            rebuild |= app.epoch_rendered(rendered);
+        }
+    }
 }
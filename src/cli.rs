@@ -0,0 +1,406 @@
+use crate::{
+    app::{LoopMode, RunOptions},
+    reftest::ReftestOptions,
+    video::{RawLayout, VideoFormat},
+    webrender_surfman::AdapterKind
+};
+use std::path::PathBuf;
+use surfman::{ContextAttributeFlags, GLVersion};
+use webrender::DebugFlags;
+
+pub const USAGE: &str = "\
+Usage: demo [OPTIONS] [EXAMPLE]
+       demo [OPTIONS] --scene <FILE>
+       demo [OPTIONS] --video <FILE> [--raw <LAYOUT>]
+       demo --list
+       demo reftest [REFTEST OPTIONS] [EXAMPLE...]
+
+Options:
+    -e, --example <NAME>     Example to run (same as the positional EXAMPLE)
+    -l, --list               List the available examples and exit
+        --scene <FILE>       Draw a YAML scene file instead of an example
+        --video <FILE>       Play a Y4M file in the yuv example at its frame rate,
+                             unless --continuous or --fps say otherwise
+        --raw <LAYOUT>       The video is raw frames laid out as FORMAT,WxH[,FPS],
+                             FORMAT being i420, nv12, i420p10, i420p12,
+                             i420p16, p010 or yuy2, e.g. nv12,1280x720,30
+    -s, --size <WxH>         Override the example's window size, e.g. 1024x768
+    -t, --title <TITLE>      Override the example's window title
+    -d, --debug <FLAGS>      Comma separated webrender debug flags, see below
+        --continuous         Redraw and tick the example every frame
+        --fps <FPS>          Like --continuous, capped to FPS frames per second
+        --headless <FRAMES>  Render FRAMES frames offscreen instead of opening a window
+    -o, --output <PATH>      Write the last headless frame to PATH as a PNG
+        --dpr <RATIO>        Device pixel ratio of headless runs, defaults to 1
+        --adapter <KIND>     hardware (default), low-power or software; falls back
+                             to software when the adapter can't be used
+        --context <FLAGS>    Comma separated GL context flags out of alpha, depth,
+                             stencil and compatibility-profile, defaults to
+                             alpha,depth,stencil
+        --gl <VERSIONS>      Desktop GL versions to try in order, defaults to 3.2
+        --gles <VERSIONS>    GLES versions to try in order, defaults to 3.0
+        --threaded           Render each window on its own thread, without the
+                             examples' custom GL drawing, not on Windows
+    -h, --help               Print this message and exit
+
+Reftest options:
+        --bless                   Write the rendered images as the new references
+        --tolerance <N>           Per channel difference ignored when comparing, defaults to 0
+        --max-differing <N>       Differing pixels tolerated per example, defaults to 0
+        --reference-dir <DIR>     Defaults to res/reftests
+        --output-dir <DIR>        Where diff images go, defaults to target/reftests
+
+Debug flags:
+    profiler, render-targets, texture-cache, gpu-cache, gpu-time-queries,
+    gpu-sample-queries, epochs, new-frame-indicator, new-scene-indicator,
+    show-overdraw, picture-caching, echo-driver-messages, disable-batching,
+    zoom";
+
+/// What the binary was asked to do.
+pub enum Command {
+    Help,
+    List,
+    Reftest(ReftestOptions),
+    Run {
+        example: String,
+        options: RunOptions,
+    },
+    Scene {
+        path: PathBuf,
+        options: RunOptions,
+    },
+    Video {
+        path: PathBuf,
+        raw: Option<RawLayout>,
+        options: RunOptions,
+    },
+}
+
+/// Parses the command line arguments, without the program name.
+pub fn parse<I: IntoIterator<Item = String>>(args: I) -> Result<Command, String> {
+    let mut args = args.into_iter().peekable();
+    if args.peek().map(String::as_str) == Some("reftest") {
+        args.next();
+        return parse_reftest(args);
+    }
+
+    let mut example = None;
+    let mut scene = None;
+    let mut video = None;
+    let mut raw = None;
+    let mut options = RunOptions::default();
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(Command::Help),
+            "-l" | "--list" => return Ok(Command::List),
+            "-e" | "--example" => example = Some(value(&arg, args.next())?),
+            "--scene" => scene = Some(PathBuf::from(value(&arg, args.next())?)),
+            "--video" => video = Some(PathBuf::from(value(&arg, args.next())?)),
+            "--raw" => raw = Some(parse_raw_layout(&value(&arg, args.next())?)?),
+            "-s" | "--size" => options.size = Some(parse_size(&value(&arg, args.next())?)?),
+            "-t" | "--title" => options.title = Some(value(&arg, args.next())?),
+            "-d" | "--debug" => options.debug_flags |= parse_debug_flags(&value(&arg, args.next())?)?,
+            "--continuous" => {
+                if options.loop_mode == LoopMode::OnEvent {
+                    options.loop_mode = LoopMode::Continuous { target_fps: None };
+                }
+            }
+            "--fps" => {
+                let target_fps = parse_number(&arg, &value(&arg, args.next())?)?;
+                options.loop_mode = LoopMode::Continuous { target_fps: Some(target_fps) };
+            }
+            "--headless" => options.headless = Some(parse_number(&arg, &value(&arg, args.next())?)?),
+            "-o" | "--output" => options.output = Some(value(&arg, args.next())?.into()),
+            "--dpr" => options.device_pixel_ratio = Some(parse_number(&arg, &value(&arg, args.next())?)?),
+            "--adapter" => options.surfman = options.surfman.adapter(parse_adapter(&value(&arg, args.next())?)?),
+            "--context" => options.surfman = options.surfman.flags(parse_context_flags(&value(&arg, args.next())?)?),
+            "--gl" => options.surfman = options.surfman.gl_versions(&parse_gl_versions(&value(&arg, args.next())?)?),
+            "--gles" => options.surfman = options.surfman.gles_versions(&parse_gl_versions(&value(&arg, args.next())?)?),
+            "--threaded" => options.threaded = true,
+            other if other.starts_with('-') => return Err(format!("Unknown option '{}'", other)),
+            _ if example.is_none() => example = Some(arg),
+            _ => return Err(format!("Unexpected argument '{}'", arg)),
+        }
+    }
+
+    if raw.is_some() && video.is_none() {
+        return Err("--raw needs a --video".to_owned());
+    }
+    match (example, scene, video) {
+        (Some(example), None, None) => Ok(Command::Run { example, options }),
+        (None, Some(path), None) => Ok(Command::Scene { path, options }),
+        (None, None, Some(path)) => Ok(Command::Video { path, raw, options }),
+        (None, None, None) => Err("No example given".to_owned()),
+        _ => Err("Give one of an example, a scene or a video".to_owned()),
+    }
+}
+
+fn parse_reftest<I: Iterator<Item = String>>(mut args: I) -> Result<Command, String> {
+    let mut options = ReftestOptions::default();
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(Command::Help),
+            "--bless" => options.bless = true,
+            "--tolerance" => options.tolerance = parse_number(&arg, &value(&arg, args.next())?)?,
+            "--max-differing" => options.max_differing_pixels = parse_number(&arg, &value(&arg, args.next())?)?,
+            "--reference-dir" => options.reference_dir = value(&arg, args.next())?.into(),
+            "--output-dir" => options.output_dir = value(&arg, args.next())?.into(),
+            other if other.starts_with('-') => return Err(format!("Unknown option '{}'", other)),
+            _ => options.examples.push(arg),
+        }
+    }
+
+    Ok(Command::Reftest(options))
+}
+
+fn value(option: &str, value: Option<String>) -> Result<String, String> {
+    value.ok_or_else(|| format!("Option '{}' requires a value", option))
+}
+
+fn parse_number<T: std::str::FromStr>(option: &str, value: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("Invalid value '{}' for option '{}'", value, option))
+}
+
+fn parse_size(size: &str) -> Result<(u32, u32), String> {
+    let mut parts = size.splitn(2, 'x');
+    let mut next = || parts.next().and_then(|part| part.trim().parse::<u32>().ok());
+    match (next(), next()) {
+        (Some(width), Some(height)) if width > 0 && height > 0 => Ok((width, height)),
+        _ => Err(format!("Invalid size '{}', expected WIDTHxHEIGHT", size)),
+    }
+}
+
+/// Parses `FORMAT,WxH[,FPS]`, 25 frames per second by default.
+fn parse_raw_layout(layout: &str) -> Result<RawLayout, String> {
+    let parts: Vec<&str> = layout.split(',').map(str::trim).collect();
+    let format = match VideoFormat::from_name(parts[0]) {
+        Some(format) => format,
+        None => {
+            let names: Vec<&str> = VideoFormat::ALL.iter().map(|format| format.name()).collect();
+            return Err(format!("Unknown video format '{}', expected one of {}", parts[0], names.join(", ")));
+        }
+    };
+    let (width, height) = match parts.get(1) {
+        Some(size) => parse_size(size)?,
+        None => return Err(format!("No frame size in '{}', expected FORMAT,WxH[,FPS]", layout)),
+    };
+    let frames_per_second = match parts.get(2) {
+        Some(fps) => parse_number("--raw", fps)?,
+        None => 25,
+    };
+    if parts.len() > 3 {
+        return Err(format!("Invalid raw layout '{}', expected FORMAT,WxH[,FPS]", layout));
+    }
+    Ok(RawLayout { format, width: width as i32, height: height as i32, frames_per_second })
+}
+
+fn parse_adapter(kind: &str) -> Result<AdapterKind, String> {
+    match kind {
+        "hardware" => Ok(AdapterKind::Hardware),
+        "low-power" => Ok(AdapterKind::LowPower),
+        "software" => Ok(AdapterKind::Software),
+        _ => Err(format!("Unknown adapter '{}', expected hardware, low-power or software", kind)),
+    }
+}
+
+fn parse_context_flags(flags: &str) -> Result<ContextAttributeFlags, String> {
+    flags
+        .split(',')
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .try_fold(ContextAttributeFlags::empty(), |flags, name| {
+            let flag = match name {
+                "alpha" => ContextAttributeFlags::ALPHA,
+                "depth" => ContextAttributeFlags::DEPTH,
+                "stencil" => ContextAttributeFlags::STENCIL,
+                "compatibility-profile" => ContextAttributeFlags::COMPATIBILITY_PROFILE,
+                _ => return Err(format!("Unknown context flag '{}'", name)),
+            };
+            Ok(flags | flag)
+        })
+}
+
+/// Parses a comma separated list of `MAJOR.MINOR` versions.
+fn parse_gl_versions(versions: &str) -> Result<Vec<GLVersion>, String> {
+    versions
+        .split(',')
+        .map(str::trim)
+        .filter(|version| !version.is_empty())
+        .map(|version| {
+            let mut parts = version.splitn(2, '.');
+            let mut next = || parts.next().and_then(|part| part.parse::<u8>().ok());
+            match (next(), next()) {
+                (Some(major), Some(minor)) => Ok(GLVersion { major, minor }),
+                _ => Err(format!("Invalid GL version '{}', expected MAJOR.MINOR", version)),
+            }
+        })
+        .collect()
+}
+
+fn parse_debug_flags(flags: &str) -> Result<DebugFlags, String> {
+    flags
+        .split(',')
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .try_fold(DebugFlags::empty(), |flags, name| {
+            let flag = match name {
+                "profiler" => DebugFlags::PROFILER_DBG,
+                "render-targets" => DebugFlags::RENDER_TARGET_DBG,
+                "texture-cache" => DebugFlags::TEXTURE_CACHE_DBG,
+                "gpu-cache" => DebugFlags::GPU_CACHE_DBG,
+                "gpu-time-queries" => DebugFlags::GPU_TIME_QUERIES,
+                "gpu-sample-queries" => DebugFlags::GPU_SAMPLE_QUERIES,
+                "epochs" => DebugFlags::EPOCHS,
+                "new-frame-indicator" => DebugFlags::NEW_FRAME_INDICATOR,
+                "new-scene-indicator" => DebugFlags::NEW_SCENE_INDICATOR,
+                "show-overdraw" => DebugFlags::SHOW_OVERDRAW,
+                "picture-caching" => DebugFlags::PICTURE_CACHING_DBG,
+                "echo-driver-messages" => DebugFlags::ECHO_DRIVER_MESSAGES,
+                "disable-batching" => DebugFlags::DISABLE_BATCHING,
+                "zoom" => DebugFlags::ZOOM_DBG,
+                _ => return Err(format!("Unknown debug flag '{}'", name)),
+            };
+            Ok(flags | flag)
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_args(args: &[&str]) -> Result<Command, String> {
+        parse(args.iter().map(|arg| arg.to_string()))
+    }
+
+    fn error(args: &[&str]) -> String {
+        match parse_args(args) {
+            Err(message) => message,
+            Ok(_) => panic!("{:?} should not parse", args),
+        }
+    }
+
+    #[test]
+    fn parses_an_example_and_its_options() {
+        let args = ["basic", "--size", "1024x768", "-t", "Title", "--fps", "30", "--headless", "2", "--dpr", "2"];
+        match parse_args(&args) {
+            Ok(Command::Run { example, options }) => {
+                assert_eq!(example, "basic");
+                assert_eq!(options.size, Some((1024, 768)));
+                assert_eq!(options.title.as_deref(), Some("Title"));
+                assert_eq!(options.loop_mode, LoopMode::Continuous { target_fps: Some(30) });
+                assert_eq!(options.headless, Some(2));
+                assert_eq!(options.device_pixel_ratio, Some(2.0));
+            }
+            _ => panic!("expected an example"),
+        }
+        match parse_args(&["--example", "yuv", "--continuous", "--threaded"]) {
+            Ok(Command::Run { example, options }) => {
+                assert_eq!(example, "yuv");
+                assert_eq!(options.loop_mode, LoopMode::Continuous { target_fps: None });
+                assert!(options.threaded);
+            }
+            _ => panic!("expected an example"),
+        }
+    }
+
+    #[test]
+    fn parses_help_list_and_reftest() {
+        assert!(matches!(parse_args(&["--list"]), Ok(Command::List)));
+        assert!(matches!(parse_args(&["basic", "-h"]), Ok(Command::Help)));
+        match parse_args(&["reftest", "--bless", "--tolerance", "2", "--max-differing", "10", "basic", "yuv"]) {
+            Ok(Command::Reftest(options)) => {
+                assert!(options.bless);
+                assert_eq!(options.tolerance, 2);
+                assert_eq!(options.max_differing_pixels, 10);
+                assert_eq!(options.examples, ["basic", "yuv"]);
+            }
+            _ => panic!("expected a reftest"),
+        }
+        assert_eq!(error(&["reftest", "--nope"]), "Unknown option '--nope'");
+    }
+
+    #[test]
+    fn parses_a_scene_or_a_video() {
+        match parse_args(&["--scene", "res/scenes/basic.yaml"]) {
+            Ok(Command::Scene { path, .. }) => assert_eq!(path, PathBuf::from("res/scenes/basic.yaml")),
+            _ => panic!("expected a scene"),
+        }
+        match parse_args(&["--video", "clip.yuv", "--raw", "nv12,64x32"]) {
+            Ok(Command::Video { path, raw, .. }) => {
+                assert_eq!(path, PathBuf::from("clip.yuv"));
+                assert_eq!(raw.map(|layout| layout.format), Some(VideoFormat::NV12));
+            }
+            _ => panic!("expected a video"),
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_arguments() {
+        assert_eq!(error(&[]), "No example given");
+        assert_eq!(error(&["--nope"]), "Unknown option '--nope'");
+        assert_eq!(error(&["basic", "yuv"]), "Unexpected argument 'yuv'");
+        assert_eq!(error(&["basic", "--size"]), "Option '--size' requires a value");
+        assert_eq!(error(&["basic", "--fps", "fast"]), "Invalid value 'fast' for option '--fps'");
+        assert_eq!(error(&["basic", "--adapter", "gpu"]), "Unknown adapter 'gpu', expected hardware, low-power or software");
+        assert_eq!(error(&["basic", "--context", "alpha,blue"]), "Unknown context flag 'blue'");
+    }
+
+    #[test]
+    fn takes_one_of_an_example_a_scene_or_a_video() {
+        let message = "Give one of an example, a scene or a video";
+        assert_eq!(error(&["basic", "--scene", "a.yaml"]), message);
+        assert_eq!(error(&["basic", "--video", "a.y4m"]), message);
+        assert_eq!(error(&["--scene", "a.yaml", "--video", "a.y4m"]), message);
+        assert_eq!(error(&["basic", "--raw", "nv12,64x32"]), "--raw needs a --video");
+    }
+
+    #[test]
+    fn parses_sizes() {
+        assert_eq!(parse_size("800x600"), Ok((800, 600)));
+        assert_eq!(parse_size(" 800 x 600 "), Ok((800, 600)));
+        for size in &["800", "0x600", "800x", "axb", "-1x600"] {
+            assert_eq!(parse_size(size), Err(format!("Invalid size '{}', expected WIDTHxHEIGHT", size)));
+        }
+    }
+
+    #[test]
+    fn parses_debug_flags() {
+        assert_eq!(parse_debug_flags("profiler, epochs,"), Ok(DebugFlags::PROFILER_DBG | DebugFlags::EPOCHS));
+        assert_eq!(parse_debug_flags(""), Ok(DebugFlags::empty()));
+        assert_eq!(parse_debug_flags("zoom,sparkles"), Err("Unknown debug flag 'sparkles'".to_owned()));
+    }
+
+    #[test]
+    fn parses_gl_versions() {
+        assert_eq!(
+            parse_gl_versions("3.2, 4.1"),
+            Ok(vec![GLVersion { major: 3, minor: 2 }, GLVersion { major: 4, minor: 1 }])
+        );
+        assert_eq!(parse_gl_versions("3"), Err("Invalid GL version '3', expected MAJOR.MINOR".to_owned()));
+        assert_eq!(parse_gl_versions("3.x"), Err("Invalid GL version '3.x', expected MAJOR.MINOR".to_owned()));
+    }
+
+    #[test]
+    fn parses_raw_layouts() {
+        assert_eq!(
+            parse_raw_layout("p010,1280x720,30"),
+            Ok(RawLayout { format: VideoFormat::P010, width: 1280, height: 720, frames_per_second: 30 })
+        );
+        assert_eq!(parse_raw_layout("i420,64x32").map(|layout| layout.frames_per_second), Ok(25));
+        assert_eq!(
+            parse_raw_layout("rgb,64x32"),
+            Err("Unknown video format 'rgb', expected one of i420, nv12, i420p10, i420p12, i420p16, p010, yuy2".to_owned())
+        );
+        assert_eq!(parse_raw_layout("nv12"), Err("No frame size in 'nv12', expected FORMAT,WxH[,FPS]".to_owned()));
+        assert_eq!(parse_raw_layout("nv12,64"), Err("Invalid size '64', expected WIDTHxHEIGHT".to_owned()));
+        assert_eq!(parse_raw_layout("nv12,64x32,x"), Err("Invalid value 'x' for option '--raw'".to_owned()));
+        assert_eq!(
+            parse_raw_layout("nv12,64x32,30,1"),
+            Err("Invalid raw layout 'nv12,64x32,30,1', expected FORMAT,WxH[,FPS]".to_owned())
+        );
+    }
+}
@@ -1,48 +1,150 @@
 use gleam::gl;
-use webrender::Renderer;
+use webrender::{Renderer, RendererOptions};
 use webrender::api::{
-    RenderApi, Transaction, FontInstanceKey,
+    RenderApi, RenderApiSender, RenderNotifier, Transaction, FontInstanceKey, FontVariation,
     DocumentId, PipelineId, DisplayListBuilder, Epoch,
-	units::LayoutSize
+    ExternalImageHandler, OutputImageHandler, ImageKey, ImageDescriptor, DirtyRect, TileSize,
+    BlobImageKey, BlobDirtyRect, ImageDescriptorFlags, ImageFormat,
+	units::{DeviceIntPoint, DeviceIntRect, DeviceIntSize, LayoutSize}
 };
 use euclid::Scale;
 use crate::{
+    error::Error,
+    fonts::{FontError, FontManager, FontSource},
+    images::Image,
+    render_thread::RenderThread,
+    text::Font,
+    vector_images::{self, TileSource, TileSources, VectorImage},
     webrender_surfman::WebrenderSurfman,
-    window::Window
+    window::{EmbedderCoordinates, WindowMethods}
+};
+use std::{
+    rc::Rc, collections::{HashMap, HashSet}, path::Path, sync::Arc, fs::File, io::{self, BufWriter},
+    sync::mpsc::{self, Receiver, Sender},
+    time::{Duration, Instant}
 };
-use std::{rc::Rc, path::PathBuf, fs::File, io::Read};
 
-pub struct Compositor {
-    window: Rc<Window>,
+/// Pixels read back from the framebuffer.
+pub struct Capture {
+    /// Size in device pixels.
+    pub size: DeviceIntSize,
+    pub device_pixel_ratio: f32,
+    /// RGBA8 pixels, top row first.
+    pub pixels: Vec<u8>,
+}
+
+impl Capture {
+    pub fn write_png(&self, path: &Path) -> io::Result<()> {
+        let file = BufWriter::new(File::create(path)?);
+        let mut encoder = png::Encoder::new(file, self.size.width as u32, self.size.height as u32);
+        encoder.set_color(png::ColorType::RGBA);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder
+            .write_header()
+            .and_then(|mut writer| writer.write_image_data(&self.pixels))
+            .map_err(|err| io::Error::new(io::ErrorKind::Other, err))
+    }
+}
+
+/// A frame the render backend built, as told to `RenderNotifier::new_frame_ready`.
+#[derive(Clone, Copy, Debug)]
+pub struct NewFrame {
+    pub document_id: DocumentId,
+    pub scrolled: bool,
+    pub composite_needed: bool,
+    /// Time the backend took to build the frame.
+    pub render_time: Option<Duration>,
+}
+
+/// Forwards to the notifier of the runner, and queues the frames for the
+/// compositor on the way.
+struct ReportingNotifier {
+    notifier: Box<dyn RenderNotifier>,
+    sender: Sender<NewFrame>,
+}
+
+impl RenderNotifier for ReportingNotifier {
+    fn clone(&self) -> Box<dyn RenderNotifier> {
+        Box::new(ReportingNotifier {
+            notifier: self.notifier.clone(),
+            sender: self.sender.clone(),
+        })
+    }
+
+    fn wake_up(&self) {
+        self.notifier.wake_up();
+    }
+
+    fn new_frame_ready(&self,
+                       document_id: DocumentId,
+                       scrolled: bool,
+                       composite_needed: bool,
+                       render_time: Option<u64>) {
+        let _ = self.sender.send(NewFrame {
+            document_id,
+            scrolled,
+            composite_needed,
+            render_time: render_time.map(Duration::from_nanos),
+        });
+        self.notifier.new_frame_ready(document_id, scrolled, composite_needed, render_time);
+    }
+}
+
+/// Wraps the notifier given to `Renderer::new`, the receiver goes to
+/// `Compositor::new`.
+pub fn reporting_notifier(notifier: Box<dyn RenderNotifier>) -> (Box<dyn RenderNotifier>, Receiver<NewFrame>) {
+    let (sender, receiver) = mpsc::channel();
+    (Box::new(ReportingNotifier { notifier, sender }), receiver)
+}
+
+/// A display list that made it to the screen.
+#[derive(Clone, Copy, Debug)]
+pub struct RenderedEpoch {
+    pub pipeline_id: PipelineId,
+    pub epoch: Epoch,
+    /// From `send_display_list` to the end of the `present` showing it.
+    pub latency: Duration,
+    /// Time the render backend took to build the last frame.
+    pub render_time: Option<Duration>,
+}
+
+/// The webrender renderer and the GL context it draws with. It stays on the
+/// thread that created it.
+pub struct LocalRenderer {
     /// The webrender renderer.
     webrender: Renderer,
-    /// The webrender interface, if enabled.
-    webrender_api: RenderApi,
     /// The surfman instance that webrender targets
     webrender_surfman: WebrenderSurfman,
     /// The GL bindings for webrender
     webrender_gl: Rc<dyn gleam::gl::Gl>,
-    /// The active webrender document.
-    document_id: DocumentId
 }
 
-impl Compositor {
+impl LocalRenderer {
+    /// Loads the GL bindings of the context and creates the renderer, with a
+    /// framebuffer of `framebuffer` device pixels.
     pub fn new(
-        window: Rc<Window>,
-        webrender: Renderer,
-        document_id: DocumentId,
-        webrender_api: RenderApi,
         webrender_surfman: WebrenderSurfman,
-        webrender_gl: Rc<dyn gleam::gl::Gl>,
-    ) -> Self {
-        Self {
-            window,
-            webrender,
-            document_id,
-            webrender_api,
-            webrender_surfman,
-            webrender_gl
+        notifier: Box<dyn RenderNotifier>,
+        options: RendererOptions,
+        framebuffer: DeviceIntSize,
+    ) -> Result<(Self, RenderApiSender), Error> {
+        // Make sure the gl context is made current.
+        webrender_surfman.make_gl_context_current().map_err(Error::Context)?;
+
+        // gleam panics when an entry point it calls wasn't loaded, so check
+        // that the context resolves one before loading the bindings.
+        if webrender_surfman.get_proc_address("glGetString").is_null() {
+            return Err(Error::GlLoad);
         }
+
+        // Get GL bindings
+        let webrender_gl = webrender_surfman.gl_bindings();
+        println!("OpenGL version {}", webrender_gl.get_string(gl::VERSION));
+        println!("Context attributes: {:?}", webrender_surfman.context_attributes());
+
+        let (webrender, sender) = Renderer::new(webrender_gl.clone(), notifier, options, None, framebuffer)?;
+
+        Ok((LocalRenderer { webrender, webrender_surfman, webrender_gl }, sender))
     }
 
     pub fn deinit(self) {
@@ -53,81 +155,110 @@ impl Compositor {
         self.webrender.deinit();
     }
 
-    pub fn set_font_instance(&mut self, (font_path, glyph_size): (PathBuf, f32), document_id: DocumentId) -> FontInstanceKey {
-        let font_key = self.webrender_api.generate_font_key();
-        println!("load font path: {:?}", font_path);
-        let font_bytes = load_file(font_path);
-
-        let mut txn = Transaction::new();
-        txn.add_raw_font(font_key, font_bytes, 0);
-
-        let font_instance_key = self.webrender_api.generate_font_instance_key();
-        txn.add_font_instance(font_instance_key, font_key, glyph_size, None, None, Vec::new());
-
-        self.webrender_api.send_transaction(document_id, txn);
-
-        println!("set font instance success, font_innstance_key={:?}", font_instance_key);
-
-        font_instance_key
+    pub fn webrender_gl(&self) -> Rc<dyn gleam::gl::Gl> {
+        self.webrender_gl.clone()
     }
 
-    pub fn get_webrender_api(&mut self) -> &mut RenderApi {
-        &mut self.webrender_api
+    pub fn webrender_surfman(&self) -> WebrenderSurfman {
+        self.webrender_surfman.clone()
     }
 
-    pub fn send_display_list(
+    pub fn set_image_handlers(
         &mut self,
-        epoch: Epoch,
-        pipeline_id: PipelineId,
-        builder: DisplayListBuilder
+        external: Option<Box<dyn ExternalImageHandler>>,
+        output: Option<Box<dyn OutputImageHandler>>,
     ) {
-        let mut txn = Transaction::new();
-        txn.set_display_list(
-            epoch,
-            None,
-            self.get_layout_size(),
-            builder.finalize(),
-            true,
-        );
-        txn.set_root_pipeline(pipeline_id);
-        txn.generate_frame();
-        self.webrender_api.send_transaction(self.document_id, txn);
+        if let Some(output_image_handler) = output {
+            self.webrender.set_output_image_handler(output_image_handler);
+        }
+        if let Some(external_image_handler) = external {
+            self.webrender.set_external_image_handler(external_image_handler);
+        }
     }
 
-    pub fn composite(&mut self) {
-        if let Err(err) = self.webrender_surfman.make_gl_context_current() {
-            println!("Failed to make GL context current: {:?}", err);
-        }
+    /// Resizes the surface to `framebuffer` device pixels.
+    pub fn resize(&self, framebuffer: DeviceIntSize) -> Result<(), Error> {
+        self.webrender_surfman.make_gl_context_current().map_err(Error::Context)?;
+        self.webrender_surfman
+            .resize(framebuffer.to_untyped())
+            .map_err(Error::Resize)
+    }
+
+    /// Renders the last frame webrender built into the framebuffer.
+    pub fn composite(&mut self, coordinates: &EmbedderCoordinates) -> Result<(), Error> {
+        self.webrender_surfman.make_gl_context_current().map_err(Error::Context)?;
         self.assert_no_gl_error();
 
         // Bind the webrender framebuffer
-        let framebuffer_object = self
-            .webrender_surfman
-            .context_surface_info()
-            .unwrap_or(None)
-            .map(|info| info.framebuffer_object)
-            .unwrap_or(0);
-        self.webrender_gl
-            .bind_framebuffer(gleam::gl::FRAMEBUFFER, framebuffer_object);
+        self.bind_framebuffer();
         self.assert_gl_framebuffer_complete();
 
         self.webrender.update();
 
-        let size = self.window.get_coordinates().framebuffer;
-        self.clear_background();
-        self.webrender.render(size).ok();
+        self.clear_background(coordinates.get_flipped_viewport());
+        self.webrender.render(coordinates.framebuffer).map_err(Error::Render)?;
+        Ok(())
     }
 
-    pub fn present(&mut self) {
+    /// The epoch of `pipeline_id` in the last frame rendered.
+    pub fn current_epoch(&self, document_id: DocumentId, pipeline_id: PipelineId) -> Option<Epoch> {
+        self.webrender.current_epoch(document_id, pipeline_id)
+    }
+
+    /// The epochs of the pipelines that changed since the last call.
+    pub fn flush_rendered_epochs(&mut self) -> Vec<(PipelineId, Epoch)> {
+        self.webrender
+            .flush_pipeline_info()
+            .epochs
+            .into_iter()
+            .map(|((pipeline_id, _), epoch)| (pipeline_id, epoch))
+            .collect()
+    }
+
+    pub fn present(&self) -> Result<(), Error> {
         // Perform the page flip. This will likely block for a while.
-        if let Err(err) = self.webrender_surfman.present() {
-            println!("Failed to present surface: {:?}", err);
+        self.webrender_surfman.present().map_err(Error::Present)
+    }
+
+    /// Reads back the viewport of the framebuffer, in device pixels.
+    pub fn read_pixels(&self, coordinates: &EmbedderCoordinates) -> Capture {
+        let viewport = coordinates.get_flipped_viewport();
+        let gl = &self.webrender_gl;
+
+        // Webrender may leave its own framebuffer bound after rendering.
+        self.bind_framebuffer();
+        gl.pixel_store_i(gl::PACK_ALIGNMENT, 1);
+
+        let pixels = gl.read_pixels(
+            viewport.origin.x,
+            viewport.origin.y,
+            viewport.size.width,
+            viewport.size.height,
+            gl::RGBA,
+            gl::UNSIGNED_BYTE,
+        );
+        self.assert_no_gl_error();
+
+        // GL rows start at the bottom.
+        let stride = viewport.size.width as usize * 4;
+        let pixels = pixels.chunks(stride).rev().flatten().cloned().collect();
+
+        Capture {
+            size: viewport.size,
+            device_pixel_ratio: coordinates.hidpi_factor.get(),
+            pixels,
         }
     }
 
-    pub fn get_layout_size(&self) -> LayoutSize {
-        let coordinates = self.window.get_coordinates();
-        coordinates.viewport.size.to_f32() / Scale::new(coordinates.hidpi_factor.get())
+    fn bind_framebuffer(&self) {
+        let framebuffer_object = self
+            .webrender_surfman
+            .context_surface_info()
+            .unwrap_or(None)
+            .map(|info| info.framebuffer_object)
+            .unwrap_or(0);
+        self.webrender_gl
+            .bind_framebuffer(gleam::gl::FRAMEBUFFER, framebuffer_object);
     }
 
     fn assert_no_gl_error(&self) {
@@ -145,7 +276,7 @@ impl Compositor {
         );
     }
 
-    fn clear_background(&self) {
+    fn clear_background(&self, viewport: DeviceIntRect) {
         let gl = &self.webrender_gl;
         self.assert_gl_framebuffer_complete();
 
@@ -155,7 +286,6 @@ impl Compositor {
         self.assert_gl_framebuffer_complete();
 
         // Make the viewport white.
-        let viewport = self.window.get_coordinates().get_flipped_viewport();
         gl.scissor(
             viewport.origin.x,
             viewport.origin.y,
@@ -170,9 +300,475 @@ impl Compositor {
     }
 }
 
-fn load_file(name: PathBuf) -> Vec<u8> {
-    let mut file = File::open(name).unwrap();
-    let mut buffer = vec![];
-    file.read_to_end(&mut buffer).unwrap();
-    buffer
+/// Where a compositor renders its frames.
+pub enum Backend {
+    /// On the thread of the event loop, when the runner asks for it.
+    Local(LocalRenderer),
+    /// On a render thread, whenever webrender built a frame.
+    Thread(RenderThread),
+}
+
+impl Backend {
+    fn current_epoch(&self, document_id: DocumentId, pipeline_id: PipelineId) -> Option<Epoch> {
+        match self {
+            Backend::Local(renderer) => renderer.current_epoch(document_id, pipeline_id),
+            Backend::Thread(thread) => thread.current_epoch(pipeline_id),
+        }
+    }
+}
+
+pub struct Compositor {
+    window: Rc<dyn WindowMethods>,
+    /// The window's coordinates as of the last `resize`.
+    coordinates: EmbedderCoordinates,
+    backend: Backend,
+    /// The webrender interface, if enabled.
+    webrender_api: RenderApi,
+    /// The active webrender document.
+    document_id: DocumentId,
+    /// The fonts and font instances loaded into the document.
+    fonts: FontManager,
+    /// The descriptors of the images added with `add_image` and
+    /// `add_vector_image`.
+    images: HashMap<ImageKey, ImageDescriptor>,
+    /// The images added with `add_vector_image`, which are blob images.
+    vector_images: HashSet<ImageKey>,
+    /// The sources of the images added with `add_generated_image`, which
+    /// are blob images too.
+    tile_sources: TileSources,
+    /// The epoch of the last display list sent for each pipeline.
+    pipeline_epochs: HashMap<PipelineId, Epoch>,
+    /// Index of the next pipeline created by `add_pipeline`.
+    next_pipeline_index: u32,
+    /// The frames reported by the render backend since the last composite.
+    new_frames: Receiver<NewFrame>,
+    /// Render time of the last frame built.
+    render_time: Option<Duration>,
+    /// Display lists sent but not rendered yet, with the time they were sent.
+    pending_epochs: Vec<(PipelineId, Epoch, Instant)>,
+    /// Display lists rendered by `composite`, until `take_rendered_epochs`.
+    rendered_epochs: Vec<(PipelineId, Epoch, Instant)>,
+    /// Set when webrender built a frame that needs compositing, or when the
+    /// window contents were invalidated. Cleared by `composite`.
+    composite_needed: bool,
+    composited_frames: u64,
+    skipped_frames: u64,
+}
+
+/// The pipeline drawing the whole document, others are embedded as iframes.
+pub const ROOT_PIPELINE_ID: PipelineId = PipelineId(0, 0);
+
+impl Compositor {
+    pub fn new(
+        window: Rc<dyn WindowMethods>,
+        coordinates: EmbedderCoordinates,
+        backend: Backend,
+        document_id: DocumentId,
+        webrender_api: RenderApi,
+        new_frames: Receiver<NewFrame>,
+        tile_sources: TileSources,
+    ) -> Self {
+        Self {
+            window,
+            coordinates,
+            backend,
+            document_id,
+            webrender_api,
+            fonts: FontManager::default(),
+            images: HashMap::new(),
+            vector_images: HashSet::new(),
+            tile_sources,
+            pipeline_epochs: HashMap::new(),
+            next_pipeline_index: ROOT_PIPELINE_ID.1 + 1,
+            new_frames,
+            render_time: None,
+            pending_epochs: Vec::new(),
+            rendered_epochs: Vec::new(),
+            composite_needed: true,
+            composited_frames: 0,
+            skipped_frames: 0,
+        }
+    }
+
+    pub fn deinit(self) {
+        match self.backend {
+            Backend::Local(renderer) => renderer.deinit(),
+            Backend::Thread(thread) => thread.exit(),
+        }
+    }
+
+    /// Returns an instance of the font from `source` at `size`. Fonts and
+    /// instances are loaded on first use and cached afterwards.
+    pub fn get_font_instance(
+        &mut self,
+        source: &FontSource,
+        size: f32,
+        variations: &[FontVariation],
+    ) -> Result<FontInstanceKey, FontError> {
+        let mut txn = Transaction::new();
+        let result = self.fonts.instance(&self.webrender_api, &mut txn, source, size, variations);
+        if !txn.is_empty() {
+            self.webrender_api.send_transaction(self.document_id, txn);
+        }
+        result
+    }
+
+    /// Deletes all fonts and instances, the next `get_font_instance` calls
+    /// read the font files again.
+    pub fn clear_fonts(&mut self) {
+        let mut txn = Transaction::new();
+        self.fonts.clear(&mut txn);
+        self.webrender_api.send_transaction(self.document_id, txn);
+    }
+
+    /// The font and size of an instance, to lay out text drawn with it.
+    pub fn font_instance(&self, font_instance_key: FontInstanceKey) -> Option<(Rc<Font>, f32)> {
+        self.fonts.font_instance(font_instance_key)
+    }
+
+    /// Uploads a decoded image, see `Image::open` and `Image::decode`.
+    /// Webrender splits images larger than its maximum texture size into
+    /// tiles of its default size. Images too large to decode whole are
+    /// better added with `add_generated_image`.
+    pub fn add_image(&mut self, image: Image) -> ImageKey {
+        let key = self.webrender_api.generate_image_key();
+        let (descriptor, data) = image.into_data();
+        let mut txn = Transaction::new();
+        txn.add_image(key, descriptor, data, None);
+        self.webrender_api.send_transaction(self.document_id, txn);
+        self.images.insert(key, descriptor);
+        key
+    }
+
+    /// Replaces the pixels of an image from `add_image`, its size and format
+    /// may change.
+    pub fn update_image(&mut self, key: ImageKey, image: Image) {
+        let (descriptor, data) = image.into_data();
+        let mut txn = Transaction::new();
+        txn.update_image(key, descriptor, data, &DirtyRect::All);
+        self.webrender_api.send_transaction(self.document_id, txn);
+        self.images.insert(key, descriptor);
+    }
+
+    /// Uploads vector artwork that the `VectorImageHandler` rasterizes at
+    /// `size` pixels, in the tiles that are in view.
+    pub fn add_vector_image(&mut self, image: &VectorImage, size: DeviceIntSize) -> ImageKey {
+        let key = self.webrender_api.generate_blob_image_key();
+        let descriptor = vector_image_descriptor(size);
+        let mut txn = Transaction::new();
+        txn.add_blob_image(
+            key,
+            descriptor,
+            Arc::new(image.encode(size)),
+            DeviceIntRect::new(DeviceIntPoint::zero(), size),
+            Some(vector_images::TILE_SIZE),
+        );
+        self.webrender_api.send_transaction(self.document_id, txn);
+        self.images.insert(key.as_image(), descriptor);
+        self.vector_images.insert(key.as_image());
+        key.as_image()
+    }
+
+    /// Adds an image of `size` pixels whose tiles of `tile_size` pixels are
+    /// generated by `source` when they come into view, so that it never is
+    /// in memory whole. Needs the `VectorImageHandler` the runner installs.
+    pub fn add_generated_image(
+        &mut self,
+        source: Arc<dyn TileSource>,
+        size: DeviceIntSize,
+        tile_size: TileSize,
+    ) -> ImageKey {
+        let key = self.webrender_api.generate_blob_image_key();
+        let descriptor = ImageDescriptor::new(size.width, size.height, ImageFormat::BGRA8, ImageDescriptorFlags::IS_OPAQUE);
+        // Registered before webrender hears of the key.
+        self.tile_sources.lock().unwrap().insert(key, source);
+        let mut txn = Transaction::new();
+        txn.add_blob_image(
+            key,
+            descriptor,
+            Arc::new(Vec::new()),
+            DeviceIntRect::new(DeviceIntPoint::zero(), size),
+            Some(tile_size),
+        );
+        self.webrender_api.send_transaction(self.document_id, txn);
+        self.images.insert(key.as_image(), descriptor);
+        key.as_image()
+    }
+
+    /// Replaces the artwork of an image from `add_vector_image`, or
+    /// rasterizes it at a new size. Other keys are ignored.
+    pub fn update_vector_image(&mut self, key: ImageKey, image: &VectorImage, size: DeviceIntSize) {
+        if !self.vector_images.contains(&key) {
+            println!("Not a vector image: {:?}", key);
+            return;
+        }
+        let descriptor = vector_image_descriptor(size);
+        let mut txn = Transaction::new();
+        txn.update_blob_image(
+            BlobImageKey(key),
+            descriptor,
+            Arc::new(image.encode(size)),
+            DeviceIntRect::new(DeviceIntPoint::zero(), size),
+            &BlobDirtyRect::All,
+        );
+        self.webrender_api.send_transaction(self.document_id, txn);
+        self.images.insert(key, descriptor);
+    }
+
+    pub fn delete_image(&mut self, key: ImageKey) {
+        if self.images.remove(&key).is_some() {
+            let mut txn = Transaction::new();
+            let generated = self.tile_sources.lock().unwrap().remove(&BlobImageKey(key)).is_some();
+            if self.vector_images.remove(&key) || generated {
+                txn.delete_blob_image(BlobImageKey(key));
+            } else {
+                txn.delete_image(key);
+            }
+            self.webrender_api.send_transaction(self.document_id, txn);
+        }
+    }
+
+    /// The size and format of an image from `add_image`, `add_vector_image`
+    /// or `add_generated_image`.
+    pub fn image_descriptor(&self, key: ImageKey) -> Option<ImageDescriptor> {
+        self.images.get(&key).cloned()
+    }
+
+    pub fn get_webrender_api(&mut self) -> &mut RenderApi {
+        &mut self.webrender_api
+    }
+
+    /// The GL bindings of the context webrender renders with, `None` when
+    /// it renders on a render thread.
+    pub fn webrender_gl(&self) -> Option<Rc<dyn gleam::gl::Gl>> {
+        match self.backend {
+            Backend::Local(ref renderer) => Some(renderer.webrender_gl()),
+            Backend::Thread(_) => None,
+        }
+    }
+
+    pub fn document_id(&self) -> DocumentId {
+        self.document_id
+    }
+
+    /// Allocates a pipeline to build a display list for, to be embedded
+    /// into another pipeline with `DisplayListBuilder::push_iframe`.
+    pub fn add_pipeline(&mut self) -> PipelineId {
+        let pipeline_id = PipelineId(ROOT_PIPELINE_ID.0, self.next_pipeline_index);
+        self.next_pipeline_index += 1;
+        pipeline_id
+    }
+
+    /// Removes a pipeline from `add_pipeline` and its display list.
+    pub fn remove_pipeline(&mut self, pipeline_id: PipelineId) {
+        self.pipeline_epochs.remove(&pipeline_id);
+        self.pending_epochs.retain(|&(pending, _, _)| pending != pipeline_id);
+        let mut txn = Transaction::new();
+        txn.remove_pipeline(pipeline_id);
+        txn.generate_frame();
+        self.webrender_api.send_transaction(self.document_id, txn);
+    }
+
+    /// Sends the display list of the builder's pipeline with the next epoch
+    /// of that pipeline, and returns the epoch. The root pipeline's viewport
+    /// is the window, others get the content size of their display list.
+    pub fn send_display_list(&mut self, builder: DisplayListBuilder) -> Epoch {
+        let (pipeline_id, content_size, display_list) = builder.finalize();
+        let epoch = match self.pipeline_epochs.get(&pipeline_id) {
+            Some(epoch) => Epoch(epoch.0 + 1),
+            None => Epoch(0),
+        };
+        self.pipeline_epochs.insert(pipeline_id, epoch);
+        self.pending_epochs.push((pipeline_id, epoch, Instant::now()));
+
+        let viewport_size = if pipeline_id == ROOT_PIPELINE_ID {
+            self.get_layout_size()
+        } else {
+            content_size
+        };
+
+        let mut txn = Transaction::new();
+        txn.set_display_list(
+            epoch,
+            None,
+            viewport_size,
+            (pipeline_id, content_size, display_list),
+            true,
+        );
+        if pipeline_id == ROOT_PIPELINE_ID {
+            txn.set_root_pipeline(pipeline_id);
+        }
+        txn.generate_frame();
+        self.webrender_api.send_transaction(self.document_id, txn);
+        epoch
+    }
+
+    /// The display lists that reached the screen since the last call, oldest
+    /// first. Call this after `present`.
+    pub fn take_rendered_epochs(&mut self) -> Vec<RenderedEpoch> {
+        let render_time = self.render_time;
+        self.rendered_epochs
+            .drain(..)
+            .map(|(pipeline_id, epoch, sent)| RenderedEpoch {
+                pipeline_id,
+                epoch,
+                latency: sent.elapsed(),
+                render_time,
+            })
+            .collect()
+    }
+
+    /// The epoch of the last display list sent for `pipeline_id`.
+    pub fn pipeline_epoch(&self, pipeline_id: PipelineId) -> Option<Epoch> {
+        self.pipeline_epochs.get(&pipeline_id).cloned()
+    }
+
+    /// Whether the last display list sent for `pipeline_id` has been
+    /// composited, or none was sent.
+    pub fn is_rendered(&self, pipeline_id: PipelineId) -> bool {
+        match self.pipeline_epoch(pipeline_id) {
+            Some(epoch) => self.backend
+                .current_epoch(self.document_id, pipeline_id)
+                .map_or(false, |current| current >= epoch),
+            None => true,
+        }
+    }
+
+    /// Resizes the surface and the document view to the window's current
+    /// size and device pixel ratio. A display list built against the new
+    /// `get_layout_size` should be sent afterwards.
+    pub fn resize(&mut self) -> Result<(), Error> {
+        let coordinates = self.window.get_coordinates()?;
+        self.coordinates = coordinates;
+        match self.backend {
+            Backend::Local(ref renderer) => renderer.resize(coordinates.framebuffer)?,
+            Backend::Thread(ref thread) => thread.resize(coordinates),
+        }
+
+        let mut txn = Transaction::new();
+        txn.set_document_view(coordinates.viewport, coordinates.hidpi_factor.get());
+        self.webrender_api.send_transaction(self.document_id, txn);
+        Ok(())
+    }
+
+    /// Whether webrender has a frame to show or the window was invalidated
+    /// since the last `composite`. With a render thread, whether it presented
+    /// frames or failed since then.
+    pub fn needs_composite(&mut self) -> bool {
+        self.receive_new_frames();
+        match self.backend {
+            Backend::Local(_) => self.composite_needed,
+            Backend::Thread(ref mut thread) => thread.has_presented(),
+        }
+    }
+
+    /// Forces the next frame to be composited, e.g. after the window system
+    /// lost the window contents.
+    pub fn invalidate(&mut self) {
+        match self.backend {
+            Backend::Local(_) => self.composite_needed = true,
+            Backend::Thread(ref thread) => thread.invalidate(),
+        }
+    }
+
+    /// Counts a frame that was not composited because nothing changed. A
+    /// render thread only composites when needed, so there is nothing to
+    /// count for it.
+    pub fn skip_frame(&mut self) {
+        if let Backend::Local(_) = self.backend {
+            self.skipped_frames += 1;
+        }
+    }
+
+    /// Number of frames composited and skipped so far.
+    pub fn frame_counts(&self) -> (u64, u64) {
+        (self.composited_frames, self.skipped_frames)
+    }
+
+    fn receive_new_frames(&mut self) {
+        for frame in self.new_frames.try_iter() {
+            self.composite_needed |= frame.composite_needed;
+            if frame.render_time.is_some() {
+                self.render_time = frame.render_time;
+            }
+        }
+    }
+
+    /// Renders the last frame webrender built. A render thread composites on
+    /// its own, this collects what it presented and the error that stopped it.
+    pub fn composite(&mut self) -> Result<(), Error> {
+        if let Backend::Local(_) = self.backend {
+            // Frames reported from here on may be built after the one
+            // rendered below, they need a composite of their own.
+            self.receive_new_frames();
+            self.composite_needed = false;
+        }
+        match self.backend {
+            Backend::Local(ref mut renderer) => {
+                renderer.composite(&self.coordinates)?;
+                self.composited_frames += 1;
+            }
+            Backend::Thread(ref mut thread) => {
+                self.composited_frames += thread.take_presented()?;
+                self.receive_new_frames();
+            }
+        }
+        self.collect_rendered_epochs();
+        Ok(())
+    }
+
+    /// Moves the pending epochs the renderer has reached to `rendered_epochs`.
+    fn collect_rendered_epochs(&mut self) {
+        let backend = &self.backend;
+        let document_id = self.document_id;
+        let rendered_epochs = &mut self.rendered_epochs;
+        self.pending_epochs.retain(|&(pipeline_id, epoch, sent)| {
+            match backend.current_epoch(document_id, pipeline_id) {
+                Some(current) if current >= epoch => {
+                    rendered_epochs.push((pipeline_id, epoch, sent));
+                    false
+                }
+                _ => true,
+            }
+        });
+    }
+
+    /// Shows the composited frame. A render thread presents its frames itself.
+    pub fn present(&mut self) -> Result<(), Error> {
+        match self.backend {
+            Backend::Local(ref renderer) => renderer.present(),
+            Backend::Thread(_) => Ok(()),
+        }
+    }
+
+    /// Reads back the viewport of the webrender framebuffer, in device pixels.
+    /// Call this after `composite` and before `present`. A render thread
+    /// composites a frame to read back, and waits for it.
+    pub fn read_pixels(&self) -> Result<Capture, Error> {
+        match self.backend {
+            Backend::Local(ref renderer) => Ok(renderer.read_pixels(&self.coordinates)),
+            Backend::Thread(ref thread) => thread.read_pixels(),
+        }
+    }
+
+    /// Writes the current frame to `path` as a PNG.
+    pub fn screenshot(&self, path: &Path) -> io::Result<()> {
+        self.read_pixels()
+            .map_err(|err| io::Error::new(io::ErrorKind::Other, err.to_string()))?
+            .write_png(path)
+    }
+
+    pub fn device_pixel_ratio(&self) -> f32 {
+        self.coordinates.hidpi_factor.get()
+    }
+
+    pub fn get_layout_size(&self) -> LayoutSize {
+        let coordinates = &self.coordinates;
+        coordinates.viewport.size.to_f32() / Scale::new(coordinates.hidpi_factor.get())
+    }
+}
+
+/// Vector images are rasterized with transparent parts.
+fn vector_image_descriptor(size: DeviceIntSize) -> ImageDescriptor {
+    ImageDescriptor::new(size.width, size.height, ImageFormat::BGRA8, ImageDescriptorFlags::empty())
 }
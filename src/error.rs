@@ -0,0 +1,114 @@
+use crate::{fonts::FontError, scene::SceneError, video::VideoError};
+use std::{fmt, io, path::PathBuf, time::Duration};
+
+/// Everything that can go wrong setting up or driving a run. Returned rather
+/// than panicking so that embedders can retry differently, e.g. with a
+/// software adapter.
+#[derive(Debug)]
+pub enum Error {
+    /// The native window could not be created.
+    Window(winit::CreationError),
+    /// The native window is gone, so it has no size.
+    WindowClosed,
+    /// No surfman connection to the display server.
+    Connection(surfman::Error),
+    /// No GPU adapter, or no native widget for the window.
+    Adapter(surfman::Error),
+    /// Creating the device, GL context or surface, or making it current.
+    Context(surfman::Error),
+    /// The GL entry points could not be loaded from the context.
+    GlLoad,
+    /// `Renderer::new` failed, usually in shader compilation.
+    Renderer(webrender::RendererError),
+    /// Errors reported by `Renderer::render` for a frame.
+    Render(Vec<webrender::RendererError>),
+    /// The surface could not be resized to the window.
+    Resize(surfman::Error),
+    /// The frame could not be presented.
+    Present(surfman::Error),
+    Font(FontError),
+    Scene(SceneError),
+    Video(VideoError),
+    /// A headless run got no frame from webrender in time.
+    FrameTimeout(Duration),
+    /// The render thread could not be started or went away.
+    RenderThread,
+    /// The render thread can't be used with this renderer configuration or
+    /// on this platform, which one is said.
+    RenderThreadUnsupported(&'static str),
+    /// No example has this name.
+    UnknownExample(String),
+    /// A capture could not be written to the `--output` path.
+    Output(PathBuf, io::Error),
+    /// Some reftests failed, they reported why.
+    ReftestFailed,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Window(err) => write!(f, "Failed to create window: {}", err),
+            Error::WindowClosed => write!(f, "The window was closed"),
+            Error::Connection(err) => write!(f, "Failed to create surfman connection: {:?}", err),
+            Error::Adapter(err) => write!(f, "Failed to create surfman adapter: {:?}", err),
+            Error::Context(err) => write!(f, "Failed to set up GL context: {:?}", err),
+            Error::GlLoad => write!(f, "Failed to load GL functions"),
+            Error::Renderer(err) => write!(f, "Unable to initialize webrender: {:?}", err),
+            Error::Render(errs) => write!(f, "Failed to render frame: {:?}", errs),
+            Error::Resize(err) => write!(f, "Failed to resize surface: {:?}", err),
+            Error::Present(err) => write!(f, "Failed to present surface: {:?}", err),
+            Error::Font(err) => err.fmt(f),
+            Error::Scene(err) => err.fmt(f),
+            Error::Video(err) => err.fmt(f),
+            Error::FrameTimeout(timeout) => write!(f, "No frame from webrender after {:?}", timeout),
+            Error::RenderThread => write!(f, "The render thread is not running"),
+            Error::RenderThreadUnsupported(what) => write!(f, "A render thread can't be used with {}", what),
+            Error::UnknownExample(name) => write!(f, "Unknown example '{}', try --list", name),
+            Error::Output(path, err) => write!(f, "Failed to write {:?}: {}", path, err),
+            Error::ReftestFailed => write!(f, "Reftests failed"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Window(err) => Some(err),
+            Error::Font(err) => Some(err),
+            Error::Scene(err) => Some(err),
+            Error::Video(err) => Some(err),
+            Error::Output(_, err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<winit::CreationError> for Error {
+    fn from(err: winit::CreationError) -> Self {
+        Error::Window(err)
+    }
+}
+
+impl From<webrender::RendererError> for Error {
+    fn from(err: webrender::RendererError) -> Self {
+        Error::Renderer(err)
+    }
+}
+
+impl From<FontError> for Error {
+    fn from(err: FontError) -> Self {
+        Error::Font(err)
+    }
+}
+
+impl From<SceneError> for Error {
+    fn from(err: SceneError) -> Self {
+        Error::Scene(err)
+    }
+}
+
+impl From<VideoError> for Error {
+    fn from(err: VideoError) -> Self {
+        Error::Video(err)
+    }
+}
@@ -1,12 +1,12 @@
 use crate::{
-    app::{App, HandyDandyRectBuilder},
+    app::{App, FrameTime, HandyDandyRectBuilder},
     compositor::Compositor
 };
 use webrender::api::{*, units::*};
 use euclid::Angle;
-use std::path::PathBuf;
+use crate::fonts::FontSource;
 
-struct Animation {
+pub struct Animation {
     property_key0: PropertyBindingKey<LayoutTransform>,
     property_key1: PropertyBindingKey<LayoutTransform>,
     property_key2: PropertyBindingKey<LayoutTransform>,
@@ -15,6 +15,24 @@ struct Animation {
     angle0: f32,
     angle1: f32,
     angle2: f32,
+    /// Whether `tick` keeps the rects rotating, toggled with space.
+    spinning: bool,
+}
+
+impl Default for Animation {
+    fn default() -> Self {
+        Animation {
+            property_key0: PropertyBindingKey::new(42), // arbitrary magic number
+            property_key1: PropertyBindingKey::new(44), // arbitrary magic number
+            property_key2: PropertyBindingKey::new(45), // arbitrary magic number
+            opacity_key: PropertyBindingKey::new(43),
+            opacity: 0.5,
+            angle0: 0.0,
+            angle1: 0.0,
+            angle2: 0.0,
+            spinning: true,
+        }
+    }
 }
 
 impl Animation {
@@ -144,7 +162,9 @@ impl App for Animation {
         Some(ColorF::new(1.0, 1.0, 1.0, 1.0))
     }
 
-    fn add_font(&self) -> Option<(PathBuf, f32)> {
+    // No text, and headless runs tick with a fixed delta, so the reftest
+    // frames are the same on every machine.
+    fn add_font(&self) -> Option<(FontSource, f32)> {
         None
     }
 
@@ -195,6 +215,10 @@ impl App for Animation {
                         rebuild_display_list = true;
                         (0.0, 0.0)
                     }
+                    winit::VirtualKeyCode::Space => {
+                        self.spinning = !self.spinning;
+                        return false;
+                    }
                     _ => return false,
                 };
 
@@ -205,18 +229,13 @@ impl App for Animation {
 
         rebuild_display_list
     }
-}
 
-pub fn run() {
-    let mut animation_app = Animation {
-        property_key0: PropertyBindingKey::new(42), // arbitrary magic number
-        property_key1: PropertyBindingKey::new(44), // arbitrary magic number
-        property_key2: PropertyBindingKey::new(45), // arbitrary magic number
-        opacity_key: PropertyBindingKey::new(43),
-        opacity: 0.5,
-        angle0: 0.0,
-        angle1: 0.0,
-        angle2: 0.0,
-    };
-    crate::app::run(&mut animation_app, None);
+    fn tick(&mut self, frame: FrameTime, api: &mut RenderApi, document_id: DocumentId) -> bool {
+        if self.spinning {
+            // Same as holding the right arrow key, ten presses per second.
+            let delta_angle = frame.delta.as_secs_f32() * 10.0;
+            self.transform(api, document_id, (delta_angle, 0.0));
+        }
+        false
+    }
 }
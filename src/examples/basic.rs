@@ -1,15 +1,18 @@
 use crate::{
     app::App,
-    compositor::Compositor
+    compositor::Compositor,
+    fonts::FontSource,
+    text::TextLayout
 };
 use webrender::api::{
     SpaceAndClipInfo, PrimitiveFlags, CommonItemProperties, DisplayListBuilder,
-    PipelineId, ColorF, GlyphInstance, FontInstanceKey, DocumentId,
+    PipelineId, ColorF, FontInstanceKey, DocumentId,
 	units::{LayoutRect, LayoutPoint, LayoutSize}
 };
-use std::{path::PathBuf, env::current_dir};
+use std::path::PathBuf;
 
-struct Basic {}
+#[derive(Default)]
+pub struct Basic {}
 
 impl App for Basic {
     const TITLE: &'static str = "Basic Example";
@@ -18,8 +21,10 @@ impl App for Basic {
         Some(ColorF::new(0.3, 0.0, 0.0, 1.0))
     }
 
-    fn add_font(&self) -> Option<(PathBuf, f32)> {
-        Some((current_dir().unwrap().join("res/fonts/FreeSans.ttf"), 32.0))
+    // The bundled font rather than a system one, so that the reftest of
+    // this example doesn't depend on the fonts installed.
+    fn add_font(&self) -> Option<(FontSource, f32)> {
+        Some((FontSource::Path(PathBuf::from("res/fonts/FreeSans.ttf")), 32.0))
     }
 
     fn build_display_list(
@@ -57,80 +62,35 @@ impl App for Basic {
 
         let text_bounds = LayoutRect::new(
             LayoutPoint::new(100.0, 50.0),
-            LayoutSize::new(700.0, 200.0)
+            LayoutSize::new(600.0, 200.0)
         );
 
-        let glyphs = vec![
-            GlyphInstance {
-                index: 48,
-                point: LayoutPoint::new(100.0, 100.0),
-            },
-            GlyphInstance {
-                index: 68,
-                point: LayoutPoint::new(150.0, 100.0),
-            },
-            GlyphInstance {
-                index: 80,
-                point: LayoutPoint::new(200.0, 100.0),
-            },
-            GlyphInstance {
-                index: 82,
-                point: LayoutPoint::new(250.0, 100.0),
-            },
-            GlyphInstance {
-                index: 81,
-                point: LayoutPoint::new(300.0, 100.0),
-            },
-            GlyphInstance {
-                index: 3,
-                point: LayoutPoint::new(350.0, 100.0),
-            },
-            GlyphInstance {
-                index: 86,
-                point: LayoutPoint::new(400.0, 100.0),
-            },
-            GlyphInstance {
-                index: 79,
-                point: LayoutPoint::new(450.0, 100.0),
-            },
-            GlyphInstance {
-                index: 72,
-                point: LayoutPoint::new(500.0, 100.0),
-            },
-            GlyphInstance {
-                index: 83,
-                point: LayoutPoint::new(550.0, 100.0),
-            },
-            GlyphInstance {
-                index: 87,
-                point: LayoutPoint::new(600.0, 100.0),
-            },
-            GlyphInstance {
-                index: 17,
-                point: LayoutPoint::new(650.0, 100.0),
-            },
-        ];
+        // Without a usable font there is only the rect.
+        let font = font_instance_key.and_then(|key| Some((key, compositor.font_instance(key)?)));
+        if let Some((font_instance_key, (font, font_size))) = font {
+            let layout = TextLayout::new(
+                &font,
+                "Mamon slept. The quick brown fox jumps over the lazy dog.",
+                font_size,
+                text_bounds.size.width,
+            );
+            let glyphs = layout.glyphs_at(text_bounds.origin);
 
-        builder.push_text(
-            &CommonItemProperties::new(
+            builder.push_text(
+                &CommonItemProperties::new(
+                    text_bounds,
+                    space_and_clip,
+                ),
                 text_bounds,
-                space_and_clip,
-            ),
-            text_bounds,
-            &glyphs,
-            font_instance_key.unwrap(),
-            ColorF::new(1.0, 1.0, 0.0, 1.0),
-            None,
-        );
-
+                &glyphs,
+                font_instance_key,
+                ColorF::new(1.0, 1.0, 0.0, 1.0),
+                None,
+            );
+        }
 
         builder.pop_stacking_context();
 
         builder
     }
 }
-
-pub fn run() {
-    let mut basic_app = Basic {};
-    crate::app::run(&mut basic_app, None);
-}
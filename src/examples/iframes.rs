@@ -0,0 +1,149 @@
+use crate::{
+    app::App,
+    compositor::{Compositor, RenderedEpoch},
+    fonts::FontSource,
+    text::TextLayout
+};
+use webrender::api::*;
+use webrender::api::units::*;
+use std::path::PathBuf;
+
+const OUTER_SIZE: (f32, f32) = (500.0, 400.0);
+const INNER_SIZE: (f32, f32) = (300.0, 200.0);
+
+fn size((width, height): (f32, f32)) -> LayoutSize {
+    LayoutSize::new(width, height)
+}
+
+/// The pipelines embedded into the root, the inner one into the outer one.
+struct Pipelines {
+    outer: PipelineId,
+    inner: PipelineId,
+}
+
+/// A root pipeline embedding a frame that embeds another one. Clicks only
+/// resend the display list of the innermost pipeline.
+#[derive(Default)]
+pub struct Iframes {
+    pipelines: Option<Pipelines>,
+    outer_sent: bool,
+    inner_dirty: bool,
+    clicks: u32,
+}
+
+impl App for Iframes {
+    const TITLE: &'static str = "Iframes Example";
+
+    fn add_font(&self) -> Option<(FontSource, f32)> {
+        Some((FontSource::Path(PathBuf::from("res/fonts/FreeSans.ttf")), 20.0))
+    }
+
+    fn build_display_list(
+        &mut self,
+        compositor: &mut Compositor,
+        pipeline_id: PipelineId,
+        _document_id: DocumentId,
+        _font_instance_key: Option<FontInstanceKey>
+    ) -> DisplayListBuilder {
+        let outer = match self.pipelines {
+            Some(ref pipelines) => pipelines.outer,
+            None => {
+                let pipelines = Pipelines {
+                    outer: compositor.add_pipeline(),
+                    inner: compositor.add_pipeline(),
+                };
+                let outer = pipelines.outer;
+                self.pipelines = Some(pipelines);
+                self.inner_dirty = true;
+                outer
+            }
+        };
+
+        let mut builder = DisplayListBuilder::new(pipeline_id, compositor.get_layout_size());
+        let space_and_clip = SpaceAndClipInfo::root_scroll(pipeline_id);
+
+        let bounds = LayoutRect::new(LayoutPoint::new(50.0, 50.0), size(OUTER_SIZE));
+        builder.push_iframe(bounds, bounds, &space_and_clip, outer, true);
+
+        builder
+    }
+
+    fn update_pipelines(
+        &mut self,
+        compositor: &mut Compositor,
+        font_instance_key: Option<FontInstanceKey>
+    ) -> bool {
+        let pipelines = match self.pipelines {
+            Some(ref pipelines) => pipelines,
+            None => return false,
+        };
+        let mut sent = false;
+
+        if !self.outer_sent {
+            let mut builder = DisplayListBuilder::new(pipelines.outer, size(OUTER_SIZE));
+            let space_and_clip = SpaceAndClipInfo::root_scroll(pipelines.outer);
+            let bounds = LayoutRect::new(LayoutPoint::zero(), size(OUTER_SIZE));
+            builder.push_rect(
+                &CommonItemProperties::new(bounds, space_and_clip),
+                bounds,
+                ColorF::new(0.2, 0.3, 0.5, 1.0),
+            );
+            let inner_bounds = LayoutRect::new(LayoutPoint::new(100.0, 100.0), size(INNER_SIZE));
+            builder.push_iframe(inner_bounds, inner_bounds, &space_and_clip, pipelines.inner, true);
+            compositor.send_display_list(builder);
+            self.outer_sent = true;
+            sent = true;
+        }
+
+        if self.inner_dirty {
+            let mut builder = DisplayListBuilder::new(pipelines.inner, size(INNER_SIZE));
+            let space_and_clip = SpaceAndClipInfo::root_scroll(pipelines.inner);
+            let bounds = LayoutRect::new(LayoutPoint::zero(), size(INNER_SIZE));
+            let shade = (self.clicks % 5) as f32 / 5.0;
+            builder.push_rect(
+                &CommonItemProperties::new(bounds, space_and_clip),
+                bounds,
+                ColorF::new(0.9, shade, 0.3, 1.0),
+            );
+
+            let font = font_instance_key.and_then(|key| Some((key, compositor.font_instance(key)?)));
+            if let Some((font_instance_key, (font, font_size))) = font {
+                let text = format!("Clicked {} times", self.clicks);
+                let layout = TextLayout::new(&font, &text, font_size, INNER_SIZE.0);
+                let origin = LayoutPoint::new(20.0, 20.0);
+                let text_bounds = LayoutRect::new(origin, layout.size);
+                builder.push_text(
+                    &CommonItemProperties::new(text_bounds, space_and_clip),
+                    text_bounds,
+                    &layout.glyphs_at(origin),
+                    font_instance_key,
+                    ColorF::BLACK,
+                    None,
+                );
+            }
+
+            let epoch = compositor.send_display_list(builder);
+            println!("Sent inner pipeline {:?} at {:?}", pipelines.inner, epoch);
+            self.inner_dirty = false;
+            sent = true;
+        }
+
+        sent
+    }
+
+    fn epoch_rendered(&mut self, rendered: RenderedEpoch) -> bool {
+        println!(
+            "{:?} of {:?} on screen after {:?}, built in {:?}",
+            rendered.epoch, rendered.pipeline_id, rendered.latency, rendered.render_time
+        );
+        false
+    }
+
+    fn on_event(&mut self, event: winit::WindowEvent, _api: &mut RenderApi, _document_id: DocumentId) -> bool {
+        if let winit::WindowEvent::MouseInput { state: winit::ElementState::Pressed, .. } = event {
+            self.clicks += 1;
+            self.inner_dirty = true;
+        }
+        false
+    }
+}
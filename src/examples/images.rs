@@ -0,0 +1,94 @@
+use crate::{
+    app::App,
+    compositor::Compositor,
+    images::Image
+};
+use std::path::Path;
+use webrender::api::*;
+use webrender::api::units::*;
+
+/// The images of `res/images`, with the checker tile repeated as the
+/// background.
+#[derive(Default)]
+pub struct Images {
+    /// The key of the background tile and of each image shown on it.
+    background: Option<ImageKey>,
+    images: Vec<ImageKey>,
+}
+
+impl Images {
+    fn load_images(&mut self, compositor: &mut Compositor) {
+        let dir = Path::new("res/images");
+        match Image::open(&dir.join("checker.png")) {
+            Ok(image) => self.background = Some(compositor.add_image(image)),
+            Err(err) => println!("{}", err),
+        }
+        for name in &["gradient.jpg", "disc.gif"] {
+            match Image::open(&dir.join(name)) {
+                Ok(image) => self.images.push(compositor.add_image(image)),
+                Err(err) => println!("{}", err),
+            }
+        }
+    }
+}
+
+impl App for Images {
+    const TITLE: &'static str = "Images Example";
+
+    fn build_display_list(
+        &mut self,
+        compositor: &mut Compositor,
+        pipeline_id: PipelineId,
+        _document_id: DocumentId,
+        _font_instance_key: Option<FontInstanceKey>
+    ) -> DisplayListBuilder {
+        if self.background.is_none() && self.images.is_empty() {
+            self.load_images(compositor);
+        }
+
+        let mut builder = DisplayListBuilder::new(pipeline_id, compositor.get_layout_size());
+        let space_and_clip = SpaceAndClipInfo::root_scroll(pipeline_id);
+
+        let bounds = LayoutRect::new(LayoutPoint::zero(), builder.content_size());
+        builder.push_simple_stacking_context(
+            bounds.origin,
+            space_and_clip.spatial_id,
+            PrimitiveFlags::IS_BACKFACE_VISIBLE,
+        );
+
+        if let Some(key) = self.background {
+            let size = compositor.image_descriptor(key).unwrap().size;
+            let tile_size = LayoutSize::new(size.width as f32, size.height as f32);
+            builder.push_repeating_image(
+                &CommonItemProperties::new(bounds, space_and_clip),
+                bounds,
+                tile_size,
+                LayoutSize::zero(),
+                ImageRendering::Pixelated,
+                AlphaType::PremultipliedAlpha,
+                key,
+                ColorF::WHITE,
+            );
+        }
+
+        // The images side by side, at their size in CSS pixels.
+        let mut origin = LayoutPoint::new(50.0, 50.0);
+        for &key in &self.images {
+            let size = compositor.image_descriptor(key).unwrap().size;
+            let image_bounds = LayoutRect::new(origin, LayoutSize::new(size.width as f32, size.height as f32));
+            builder.push_image(
+                &CommonItemProperties::new(image_bounds, space_and_clip),
+                image_bounds,
+                ImageRendering::Auto,
+                AlphaType::PremultipliedAlpha,
+                key,
+                ColorF::WHITE,
+            );
+            origin.x += image_bounds.size.width + 50.0;
+        }
+
+        builder.pop_stacking_context();
+
+        builder
+    }
+}
@@ -0,0 +1,148 @@
+use crate::{
+    app::App,
+    compositor::Compositor,
+    vector_images::TileSource
+};
+use std::sync::Arc;
+use webrender::api::*;
+use webrender::api::units::*;
+use winit::dpi::LogicalPosition;
+
+/// Larger than the maximum texture size of any GPU, and than what would be
+/// reasonable to keep in memory.
+const MAP_SIZE: i32 = 20480;
+const TILE_SIZE: TileSize = 512;
+const GRID_SPACING: i32 = 256;
+
+/// A generated relief map of `MAP_SIZE` pixels square in a scroll frame the
+/// size of the window. Scroll it with the mouse wheel or the arrow keys,
+/// webrender generates the tiles as they come into view.
+#[derive(Default)]
+pub struct Map {
+    image_key: Option<ImageKey>,
+    cursor_position: WorldPoint,
+}
+
+/// Water, lowlands and hills with contour lines and a grid, made of two
+/// waves so that tiles are quick to generate.
+struct Relief;
+
+impl TileSource for Relief {
+    fn generate(&self, rect: DeviceIntRect) -> Vec<u8> {
+        let columns: Vec<f32> = (rect.min_x()..rect.max_x()).map(|x| (x as f32 * 0.0021).sin()).collect();
+        let mut pixels = Vec::with_capacity(rect.size.area() as usize * 4);
+        for y in rect.min_y()..rect.max_y() {
+            let row = (y as f32 * 0.0013).cos();
+            for (x, column) in (rect.min_x()..).zip(&columns) {
+                // Between -1 and 1.
+                let height = (row + column) / 2.0;
+                let [r, g, b] = if x % GRID_SPACING == 0 || y % GRID_SPACING == 0 {
+                    [40, 40, 40]
+                } else if height < -0.3 {
+                    [40, 90, (200.0 + height * 80.0) as u8]
+                } else if (height * 10.0).fract().abs() < 0.03 {
+                    [110, 80, 50]
+                } else {
+                    let hill = (height + 0.3) / 1.3;
+                    [(90.0 + hill * 130.0) as u8, (160.0 - hill * 40.0) as u8, 70]
+                };
+                pixels.extend_from_slice(&[b, g, r, 255]);
+            }
+        }
+        pixels
+    }
+}
+
+impl App for Map {
+    const TITLE: &'static str = "Map Example";
+
+    fn build_display_list(
+        &mut self,
+        compositor: &mut Compositor,
+        pipeline_id: PipelineId,
+        _document_id: DocumentId,
+        _font_instance_key: Option<FontInstanceKey>
+    ) -> DisplayListBuilder {
+        let image_key = match self.image_key {
+            Some(image_key) => image_key,
+            None => {
+                let size = DeviceIntSize::new(MAP_SIZE, MAP_SIZE);
+                let image_key = compositor.add_generated_image(Arc::new(Relief), size, TILE_SIZE);
+                self.image_key = Some(image_key);
+                image_key
+            }
+        };
+
+        let mut builder = DisplayListBuilder::new(pipeline_id, compositor.get_layout_size());
+        let root_space_and_clip = SpaceAndClipInfo::root_scroll(pipeline_id);
+
+        let viewport = LayoutRect::new(LayoutPoint::zero(), builder.content_size());
+        builder.push_simple_stacking_context(
+            viewport.origin,
+            root_space_and_clip.spatial_id,
+            PrimitiveFlags::IS_BACKFACE_VISIBLE,
+        );
+
+        let map_bounds = LayoutRect::new(LayoutPoint::zero(), LayoutSize::new(MAP_SIZE as f32, MAP_SIZE as f32));
+        let space_and_clip = builder.define_scroll_frame(
+            &root_space_and_clip,
+            None,
+            map_bounds,
+            viewport,
+            ScrollSensitivity::ScriptAndInputEvents,
+            LayoutVector2D::zero(),
+        );
+        builder.push_image(
+            &CommonItemProperties::new(map_bounds, space_and_clip),
+            map_bounds,
+            ImageRendering::Auto,
+            AlphaType::PremultipliedAlpha,
+            image_key,
+            ColorF::WHITE,
+        );
+
+        builder.pop_stacking_context();
+
+        builder
+    }
+
+    fn on_event(&mut self, event: winit::WindowEvent, api: &mut RenderApi, document_id: DocumentId) -> bool {
+        const LINE_HEIGHT: f32 = 38.0;
+        const KEY_STEP: f32 = 100.0;
+
+        let delta = match event {
+            winit::WindowEvent::KeyboardInput {
+                input: winit::KeyboardInput {
+                    state: winit::ElementState::Pressed,
+                    virtual_keycode: Some(key),
+                    ..
+                },
+                ..
+            } => match key {
+                winit::VirtualKeyCode::Down => Some((0.0, -KEY_STEP)),
+                winit::VirtualKeyCode::Up => Some((0.0, KEY_STEP)),
+                winit::VirtualKeyCode::Right => Some((-KEY_STEP, 0.0)),
+                winit::VirtualKeyCode::Left => Some((KEY_STEP, 0.0)),
+                _ => None,
+            },
+            winit::WindowEvent::CursorMoved { position: LogicalPosition { x, y }, .. } => {
+                self.cursor_position = WorldPoint::new(x as f32, y as f32);
+                None
+            }
+            winit::WindowEvent::MouseWheel { delta, .. } => match delta {
+                winit::MouseScrollDelta::LineDelta(dx, dy) => Some((dx * LINE_HEIGHT, dy * LINE_HEIGHT)),
+                winit::MouseScrollDelta::PixelDelta(pos) => Some((pos.x as f32, pos.y as f32)),
+            },
+            _ => None,
+        };
+
+        if let Some((dx, dy)) = delta {
+            let mut txn = Transaction::new();
+            txn.scroll(ScrollLocation::Delta(LayoutVector2D::new(dx, dy)), self.cursor_position);
+            txn.generate_frame();
+            api.send_transaction(document_id, txn);
+        }
+
+        false
+    }
+}
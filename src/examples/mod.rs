@@ -1,4 +1,53 @@
+use crate::{app::{self, RunOptions}, compositor::Capture, error::Error};
+
 pub mod animation;
 pub mod basic;
+pub mod iframes;
+pub mod images;
+pub mod map;
 pub mod scrolling;
+pub mod surfaces;
+pub mod vector;
+pub mod windows;
 pub mod yuv;
+
+/// An entry of the example registry, selectable from the command line.
+pub struct Example {
+    pub name: &'static str,
+    pub description: &'static str,
+    pub run: fn(&RunOptions) -> Result<(), Error>,
+    /// Renders the given number of frames offscreen.
+    pub render: fn(&RunOptions, usize) -> Result<Capture, Error>,
+}
+
+// Registers `App` implementations under the name of their module. Adding an
+// example only takes a `pub mod` above and a line in the invocation below.
+macro_rules! examples {
+    ($($module:ident::$app:ident => $description:expr,)*) => {
+        pub static EXAMPLES: &[Example] = &[
+            $(Example {
+                name: stringify!($module),
+                description: $description,
+                run: app::run_example::<$module::$app>,
+                render: app::render_example::<$module::$app>,
+            },)*
+        ];
+    };
+}
+
+examples! {
+    animation::Animation => "Rounded rects driven by dynamic transform and opacity properties",
+    basic::Basic => "A rect and a line of text",
+    iframes::Iframes => "Nested iframes of pipelines that update independently",
+    images::Images => "Decoded PNG, JPEG and GIF images over a repeating background",
+    map::Map => "A scrolling 20480 pixel map, its tiles generated as they come into view",
+    scrolling::ScrollPanel => "Nested scroll frames, a sticky frame and hit testing",
+    surfaces::Surfaces => "A second GL context shown through a shared surface texture",
+    vector::Vector => "Paths and gradients rasterized in tiles by the blob image handler",
+    windows::Windows => "Color swatches and an inspector window showing the selected one",
+    yuv::Yuv => "Color bars, or the video of --video, beside a copy in a YUV format picked at runtime",
+}
+
+pub fn find(name: &str) -> Option<&'static Example> {
+    EXAMPLES.iter().find(|example| example.name == name)
+}
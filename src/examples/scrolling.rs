@@ -7,16 +7,26 @@ use euclid::SideOffsets2D;
 use webrender::api::*;
 use webrender::api::units::*;
 use winit::dpi::LogicalPosition;
-use std::path::PathBuf;
+use crate::fonts::FontSource;
 
-struct ScrollPanel {
+pub struct ScrollPanel {
     cursor_position: WorldPoint,
 }
 
+impl Default for ScrollPanel {
+    fn default() -> Self {
+        ScrollPanel {
+            cursor_position: WorldPoint::zero()
+        }
+    }
+}
+
 impl App for ScrollPanel {
     const TITLE: &'static str = "Scrolling Example";
 
-    fn add_font(&self) -> Option<(PathBuf, f32)> {
+    // Only rects, which the reftest renders the same whatever fonts the
+    // machine has.
+    fn add_font(&self) -> Option<(FontSource, f32)> {
         None
     }
 	fn build_display_list(
@@ -223,11 +233,3 @@ impl App for ScrollPanel {
         false
     }
 }
-
-pub fn run() {
-    let mut app = ScrollPanel {
-        cursor_position: WorldPoint::zero()
-    };
-
-    crate::app::run(&mut app, None);
-}
@@ -0,0 +1,221 @@
+use crate::{
+    app::{App, FrameTime},
+    compositor::Compositor,
+    external_images::ExternalImages,
+    shared_surface::SurfaceProducer,
+    webrender_surfman::WebrenderSurfman
+};
+use euclid::default::Size2D;
+use gleam::gl;
+use webrender::api::*;
+use webrender::api::units::*;
+
+const PRODUCER_SIZE: (i32, i32) = (320, 240);
+const PRODUCER_IMAGE_ID: ExternalImageId = ExternalImageId(0);
+const STRIP_IMAGE_ID: ExternalImageId = ExternalImageId(1);
+const BAR_WIDTH: i32 = 40;
+const FLOOR_HEIGHT: i32 = 20;
+const STRIP_HEIGHT: i32 = 16;
+
+/// A second GL context drawing a bar over a floor, shown in the scene
+/// through a shared surface, above a strip drawn on the CPU marking where the
+/// bar is. Clicks move the bar, `--continuous` animates it.
+#[derive(Default)]
+pub struct Surfaces {
+    images: Option<ExternalImages>,
+    producer: Option<SurfaceProducer>,
+    texture_target: Option<TextureTarget>,
+    image_key: Option<ImageKey>,
+    strip_image_key: Option<ImageKey>,
+    position: i32,
+}
+
+impl Surfaces {
+    /// The BGRA pixels of the strip, in the colors of the producer's frame.
+    fn draw_strip(&self) -> Vec<u8> {
+        let (width, _) = PRODUCER_SIZE;
+        let bar = self.position % (width - BAR_WIDTH);
+        let mut pixels = Vec::with_capacity((width * STRIP_HEIGHT * 4) as usize);
+        for _ in 0..STRIP_HEIGHT {
+            for x in 0..width {
+                let bgra = if x >= bar && x < bar + BAR_WIDTH {
+                    [0x33, 0xcc, 0xff, 0xff]
+                } else {
+                    [0x4d, 0x1a, 0x1a, 0xff]
+                };
+                pixels.extend_from_slice(&bgra);
+            }
+        }
+        pixels
+    }
+
+    /// Draws the next frame of the producer and presents it.
+    fn produce_frame(&self) {
+        let producer = match self.producer {
+            Some(ref producer) => producer,
+            None => return,
+        };
+        let (width, height) = PRODUCER_SIZE;
+        let result = producer.begin_frame().and_then(|gl| {
+            gl.clear_color(0.1, 0.1, 0.3, 1.0);
+            gl.clear(gl::COLOR_BUFFER_BIT);
+            gl.enable(gl::SCISSOR_TEST);
+            // GL rows start at the bottom, the floor has to end up there.
+            gl.scissor(0, 0, width, FLOOR_HEIGHT);
+            gl.clear_color(0.2, 0.7, 0.3, 1.0);
+            gl.clear(gl::COLOR_BUFFER_BIT);
+            gl.scissor(self.position % (width - BAR_WIDTH), FLOOR_HEIGHT, BAR_WIDTH, height - FLOOR_HEIGHT);
+            gl.clear_color(1.0, 0.8, 0.2, 1.0);
+            gl.clear(gl::COLOR_BUFFER_BIT);
+            gl.disable(gl::SCISSOR_TEST);
+            producer.present()
+        });
+        if let Err(err) = result {
+            println!("Failed to produce a frame: {}", err);
+        }
+    }
+}
+
+impl App for Surfaces {
+    const TITLE: &'static str = "Surfaces Example";
+
+    fn build_display_list(
+        &mut self,
+        compositor: &mut Compositor,
+        pipeline_id: PipelineId,
+        document_id: DocumentId,
+        _font_instance_key: Option<FontInstanceKey>
+    ) -> DisplayListBuilder {
+        let mut builder = DisplayListBuilder::new(pipeline_id, compositor.get_layout_size());
+        let space_and_clip = SpaceAndClipInfo::root_scroll(pipeline_id);
+
+        let images = match self.images {
+            Some(ref images) => images,
+            None => {
+                println!("No external images, they need the GL context of the event loop's thread");
+                return builder;
+            }
+        };
+
+        // Webrender caches what it drew of the images, updating them with
+        // the same data tells it a new frame was produced.
+        let mut txn = Transaction::new();
+        let api = compositor.get_webrender_api();
+        let (width, height) = PRODUCER_SIZE;
+
+        if let Some(texture_target) = self.texture_target {
+            let descriptor = ImageDescriptor::new(width, height, ImageFormat::BGRA8, ImageDescriptorFlags::IS_OPAQUE);
+            let data = ImageData::External(ExternalImageData {
+                id: PRODUCER_IMAGE_ID,
+                channel_index: 0,
+                image_type: ExternalImageType::TextureHandle(texture_target),
+            });
+            let image_key = set_image(&mut self.image_key, api, &mut txn, descriptor, data);
+
+            let bounds = LayoutRect::new(LayoutPoint::new(100.0, 100.0), LayoutSize::new(width as f32, height as f32));
+            builder.push_image(
+                &CommonItemProperties::new(bounds, space_and_clip),
+                bounds,
+                ImageRendering::Auto,
+                AlphaType::PremultipliedAlpha,
+                image_key,
+                ColorF::WHITE,
+            );
+        }
+
+        // Webrender reads the pixels of a buffer image when it uploads it.
+        images.set_buffer(STRIP_IMAGE_ID, 0, self.draw_strip());
+        let descriptor = ImageDescriptor::new(width, STRIP_HEIGHT, ImageFormat::BGRA8, ImageDescriptorFlags::IS_OPAQUE);
+        let data = ImageData::External(ExternalImageData {
+            id: STRIP_IMAGE_ID,
+            channel_index: 0,
+            image_type: ExternalImageType::Buffer,
+        });
+        let strip_image_key = set_image(&mut self.strip_image_key, api, &mut txn, descriptor, data);
+        api.send_transaction(document_id, txn);
+
+        let bounds = LayoutRect::new(
+            LayoutPoint::new(100.0, 110.0 + height as f32),
+            LayoutSize::new(width as f32, STRIP_HEIGHT as f32),
+        );
+        builder.push_image(
+            &CommonItemProperties::new(bounds, space_and_clip),
+            bounds,
+            ImageRendering::Auto,
+            AlphaType::PremultipliedAlpha,
+            strip_image_key,
+            ColorF::WHITE,
+        );
+
+        builder
+    }
+
+    fn on_event(&mut self, event: winit::WindowEvent, _api: &mut RenderApi, _document_id: DocumentId) -> bool {
+        match event {
+            winit::WindowEvent::MouseInput { state: winit::ElementState::Pressed, .. } => {
+                self.position += BAR_WIDTH / 2;
+                self.produce_frame();
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn tick(&mut self, _: FrameTime, _: &mut RenderApi, _: DocumentId) -> bool {
+        self.position += 2;
+        self.produce_frame();
+        true
+    }
+
+    fn get_image_handlers(
+        &mut self,
+        _gl: &dyn gl::Gl,
+        webrender_surfman: &WebrenderSurfman,
+    ) -> (
+        Option<Box<dyn ExternalImageHandler>>,
+        Option<Box<dyn OutputImageHandler>>,
+    ) {
+        let images = ExternalImages::new(webrender_surfman);
+        let (width, height) = PRODUCER_SIZE;
+        match SurfaceProducer::new(webrender_surfman, Size2D::new(width, height)) {
+            Ok(producer) => {
+                images.set_producer(PRODUCER_IMAGE_ID, 0, &producer);
+                self.texture_target = Some(images.surface_texture_target());
+                self.producer = Some(producer);
+
+                // The first frame, shown until the next one is presented.
+                self.produce_frame();
+                if let Err(err) = webrender_surfman.make_gl_context_current() {
+                    println!("Failed to make GL context current: {:?}", err);
+                }
+            }
+            Err(err) => println!("Failed to create the producer context: {}", err),
+        }
+
+        let handler = images.handler();
+        self.images = Some(images);
+        (Some(handler), None)
+    }
+}
+
+/// Adds the image under `image_key`, or updates it if it was added before.
+fn set_image(
+    image_key: &mut Option<ImageKey>,
+    api: &RenderApi,
+    txn: &mut Transaction,
+    descriptor: ImageDescriptor,
+    data: ImageData,
+) -> ImageKey {
+    match *image_key {
+        Some(image_key) => {
+            txn.update_image(image_key, descriptor, data, &DirtyRect::All);
+            image_key
+        }
+        None => {
+            let key = api.generate_image_key();
+            txn.add_image(key, descriptor, data, None);
+            *image_key = Some(key);
+            key
+        }
+    }
+}
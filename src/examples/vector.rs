@@ -0,0 +1,158 @@
+use crate::{
+    app::App,
+    compositor::Compositor,
+    vector_images::{Paint, VectorImage, VectorPath}
+};
+use euclid::default::Point2D;
+use webrender::api::*;
+use webrender::api::units::*;
+
+/// A landscape drawn with paths and gradients, rasterized by the blob image
+/// handler. `+` and `-` zoom, which rasterizes it again at the new size so
+/// that it stays sharp.
+pub struct Vector {
+    artwork: VectorImage,
+    zoom: f32,
+    image: Option<(ImageKey, DeviceIntSize)>,
+}
+
+impl Default for Vector {
+    fn default() -> Self {
+        Vector {
+            artwork: landscape(),
+            zoom: 1.0,
+            image: None,
+        }
+    }
+}
+
+fn stop(offset: f32, r: f32, g: f32, b: f32) -> GradientStop {
+    GradientStop { offset, color: ColorF::new(r, g, b, 1.0) }
+}
+
+fn landscape() -> VectorImage {
+    let mut image = VectorImage::new(400.0, 300.0);
+    image.fill(
+        VectorPath::rect(0.0, 0.0, 400.0, 300.0),
+        Paint::LinearGradient {
+            start: Point2D::new(0.0, 0.0),
+            end: Point2D::new(0.0, 200.0),
+            stops: vec![stop(0.0, 0.2, 0.4, 0.9), stop(1.0, 0.9, 0.8, 0.9)],
+        },
+    );
+    image.fill(
+        VectorPath::circle(290.0, 90.0, 40.0),
+        Paint::RadialGradient {
+            center: Point2D::new(290.0, 90.0),
+            radius: 40.0,
+            stops: vec![stop(0.0, 1.0, 1.0, 0.6), stop(1.0, 1.0, 0.6, 0.1)],
+        },
+    );
+    image.fill(
+        VectorPath::new()
+            .move_to(0.0, 300.0)
+            .line_to(0.0, 190.0)
+            .quad_to(110.0, 110.0, 220.0, 190.0)
+            .quad_to(320.0, 150.0, 400.0, 200.0)
+            .line_to(400.0, 300.0)
+            .close(),
+        Paint::LinearGradient {
+            start: Point2D::new(0.0, 150.0),
+            end: Point2D::new(0.0, 300.0),
+            stops: vec![stop(0.0, 0.3, 0.7, 0.3), stop(1.0, 0.1, 0.3, 0.1)],
+        },
+    );
+    // A river winding towards the viewer.
+    image.stroke(
+        VectorPath::new()
+            .move_to(180.0, 200.0)
+            .cubic_to(120.0, 230.0, 280.0, 250.0, 200.0, 300.0),
+        6.0,
+        Paint::Solid(ColorF::new(0.3, 0.5, 0.9, 1.0)),
+    );
+    image.stroke(
+        VectorPath::new()
+            .move_to(60.0, 60.0)
+            .quad_to(70.0, 52.0, 80.0, 60.0)
+            .quad_to(90.0, 52.0, 100.0, 60.0),
+        2.0,
+        Paint::Solid(ColorF::new(0.1, 0.1, 0.2, 1.0)),
+    );
+    image
+}
+
+impl App for Vector {
+    const TITLE: &'static str = "Vector Example";
+
+    fn build_display_list(
+        &mut self,
+        compositor: &mut Compositor,
+        pipeline_id: PipelineId,
+        _document_id: DocumentId,
+        _font_instance_key: Option<FontInstanceKey>
+    ) -> DisplayListBuilder {
+        let view_box = self.artwork.view_box();
+        let layout_size = LayoutSize::new(view_box.width * self.zoom, view_box.height * self.zoom);
+        let scale = compositor.device_pixel_ratio();
+        let size = DeviceIntSize::new(
+            (layout_size.width * scale).round() as i32,
+            (layout_size.height * scale).round() as i32,
+        );
+
+        let image_key = match self.image {
+            Some((image_key, image_size)) => {
+                if image_size != size {
+                    compositor.update_vector_image(image_key, &self.artwork, size);
+                }
+                image_key
+            }
+            None => compositor.add_vector_image(&self.artwork, size),
+        };
+        self.image = Some((image_key, size));
+
+        let mut builder = DisplayListBuilder::new(pipeline_id, compositor.get_layout_size());
+        let space_and_clip = SpaceAndClipInfo::root_scroll(pipeline_id);
+
+        builder.push_simple_stacking_context(
+            LayoutPoint::zero(),
+            space_and_clip.spatial_id,
+            PrimitiveFlags::IS_BACKFACE_VISIBLE,
+        );
+
+        let bounds = LayoutRect::new(LayoutPoint::new(50.0, 50.0), layout_size);
+        builder.push_image(
+            &CommonItemProperties::new(bounds, space_and_clip),
+            bounds,
+            ImageRendering::Auto,
+            AlphaType::PremultipliedAlpha,
+            image_key,
+            ColorF::WHITE,
+        );
+
+        builder.pop_stacking_context();
+
+        builder
+    }
+
+    fn on_event(&mut self, event: winit::WindowEvent, _api: &mut RenderApi, _document_id: DocumentId) -> bool {
+        match event {
+            winit::WindowEvent::KeyboardInput {
+                input: winit::KeyboardInput {
+                    state: winit::ElementState::Pressed,
+                    virtual_keycode: Some(key),
+                    ..
+                },
+                ..
+            } => {
+                self.zoom = match key {
+                    winit::VirtualKeyCode::Equals => (self.zoom * 1.25).min(8.0),
+                    winit::VirtualKeyCode::Minus => (self.zoom / 1.25).max(0.25),
+                    winit::VirtualKeyCode::Key0 => 1.0,
+                    _ => return false,
+                };
+                true
+            }
+            _ => false,
+        }
+    }
+}
@@ -0,0 +1,148 @@
+use crate::{
+    app::{App, WindowOptions},
+    compositor::Compositor,
+    fonts::{FontDescriptor, FontSource},
+    text::TextLayout
+};
+use webrender::api::*;
+use webrender::api::units::*;
+use winit::dpi::LogicalPosition;
+
+const SWATCH_SIZE: f32 = 100.0;
+const SWATCH_COLORS: [ColorF; 4] = [
+    ColorF { r: 0.9, g: 0.2, b: 0.2, a: 1.0 },
+    ColorF { r: 0.2, g: 0.7, b: 0.3, a: 1.0 },
+    ColorF { r: 0.2, g: 0.4, b: 0.9, a: 1.0 },
+    ColorF { r: 0.9, g: 0.8, b: 0.2, a: 1.0 },
+];
+
+/// A content window of color swatches and an inspector window showing the
+/// swatch that was clicked last.
+pub struct Windows {
+    cursor_position: LayoutPoint,
+    selected: Option<usize>,
+}
+
+impl Default for Windows {
+    fn default() -> Self {
+        Windows {
+            cursor_position: LayoutPoint::zero(),
+            selected: None,
+        }
+    }
+}
+
+fn swatch_rect(index: usize) -> LayoutRect {
+    let x = 50.0 + index as f32 * (SWATCH_SIZE + 20.0);
+    LayoutRect::new(LayoutPoint::new(x, 50.0), LayoutSize::new(SWATCH_SIZE, SWATCH_SIZE))
+}
+
+impl App for Windows {
+    const TITLE: &'static str = "Windows Example";
+    const SIZE: (u32, u32) = (560, 200);
+
+    fn add_font(&self) -> Option<(FontSource, f32)> {
+        Some((FontSource::System(FontDescriptor::new("sans-serif")), 18.0))
+    }
+
+    fn extra_windows(&self) -> Vec<WindowOptions> {
+        vec![WindowOptions {
+            title: "Inspector".to_owned(),
+            size: (300, 200),
+        }]
+    }
+
+    fn build_display_list(
+        &mut self,
+        compositor: &mut Compositor,
+        pipeline_id: PipelineId,
+        _document_id: DocumentId,
+        _font_instance_key: Option<FontInstanceKey>
+    ) -> DisplayListBuilder {
+        let mut builder = DisplayListBuilder::new(pipeline_id, compositor.get_layout_size());
+        let space_and_clip = SpaceAndClipInfo::root_scroll(pipeline_id);
+
+        for (index, color) in SWATCH_COLORS.iter().enumerate() {
+            let bounds = swatch_rect(index);
+            if self.selected == Some(index) {
+                let outline = bounds.inflate(4.0, 4.0);
+                builder.push_rect(&CommonItemProperties::new(outline, space_and_clip), outline, ColorF::WHITE);
+            }
+            builder.push_rect(&CommonItemProperties::new(bounds, space_and_clip), bounds, *color);
+        }
+
+        builder
+    }
+
+    fn build_extra_display_list(
+        &mut self,
+        _index: usize,
+        compositor: &mut Compositor,
+        pipeline_id: PipelineId,
+        _document_id: DocumentId,
+        font_instance_key: Option<FontInstanceKey>
+    ) -> DisplayListBuilder {
+        let mut builder = DisplayListBuilder::new(pipeline_id, compositor.get_layout_size());
+        let space_and_clip = SpaceAndClipInfo::root_scroll(pipeline_id);
+
+        let label = match self.selected {
+            Some(index) => {
+                let bounds = LayoutRect::new(LayoutPoint::new(20.0, 60.0), LayoutSize::new(260.0, 120.0));
+                builder.push_rect(&CommonItemProperties::new(bounds, space_and_clip), bounds, SWATCH_COLORS[index]);
+                let ColorF { r, g, b, .. } = SWATCH_COLORS[index];
+                format!("Swatch {}: rgb({:.1}, {:.1}, {:.1})", index + 1, r, g, b)
+            }
+            None => "Click a swatch".to_owned(),
+        };
+
+        let mut font = font_instance_key.and_then(|key| Some((key, compositor.font_instance(key)?)));
+        // The prompt is in whatever bold italic sans-serif font the system
+        // has, the label of a swatch in the window's font.
+        if let (None, Some((_, (_, font_size)))) = (self.selected, &font) {
+            let prompt_font = FontSource::System(FontDescriptor::new("sans-serif").bold().italic());
+            match compositor.get_font_instance(&prompt_font, *font_size, &[]) {
+                Ok(key) => match compositor.font_instance(key) {
+                    Some(prompt_font) => font = Some((key, prompt_font)),
+                    None => println!("Drawing the prompt in the regular font, the bold italic one could not be parsed"),
+                },
+                Err(err) => println!("{}", err),
+            }
+        }
+        if let Some((font_instance_key, (font, font_size))) = font {
+            let layout = TextLayout::new(&font, &label, font_size, 260.0);
+            let origin = LayoutPoint::new(20.0, 20.0);
+            let bounds = LayoutRect::new(origin, layout.size);
+            builder.push_text(
+                &CommonItemProperties::new(bounds, space_and_clip),
+                bounds,
+                &layout.glyphs_at(origin),
+                font_instance_key,
+                ColorF::WHITE,
+                None,
+            );
+        }
+
+        builder
+    }
+
+    fn on_event(&mut self, event: winit::WindowEvent, _api: &mut RenderApi, _document_id: DocumentId) -> bool {
+        match event {
+            winit::WindowEvent::CursorMoved { position: LogicalPosition { x, y }, .. } => {
+                self.cursor_position = LayoutPoint::new(x as f32, y as f32);
+                false
+            }
+            winit::WindowEvent::MouseInput {
+                state: winit::ElementState::Pressed,
+                button: winit::MouseButton::Left,
+                ..
+            } => {
+                let selected = (0..SWATCH_COLORS.len())
+                    .find(|index| swatch_rect(*index).contains(self.cursor_position));
+                let changed = selected != self.selected;
+                self.selected = selected;
+                changed
+            }
+            _ => false,
+        }
+    }
+}
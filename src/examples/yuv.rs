@@ -1,88 +1,341 @@
-use crate::{app::App, compositor::Compositor};
+use crate::{
+    app::{App, FrameTime, LoopMode, RunOptions},
+    compositor::Compositor,
+    external_images::ExternalImages,
+    video::{PlaneLayout, VideoFormat, VideoFrame, VideoInfo, VideoSource},
+    webrender_surfman::WebrenderSurfman
+};
+use euclid::default::Size2D;
 use gleam::gl;
+use std::time::Duration;
 use webrender::api::{
-    units::{LayoutPoint, LayoutRect, LayoutSize, TexelRect},
+    units::{LayoutPoint, LayoutRect, LayoutSize},
     *,
 };
 
-fn init_gl_texture(
-    id: gl::GLuint,
-    internal: gl::GLenum,
-    external: gl::GLenum,
-    bytes: &[u8],
-    gl: &dyn gl::Gl,
-) {
-    gl.bind_texture(gl::TEXTURE_2D, id);
-    gl.tex_parameter_i(
-        gl::TEXTURE_2D,
-        gl::TEXTURE_MAG_FILTER,
-        gl::LINEAR as gl::GLint,
-    );
-    gl.tex_parameter_i(
-        gl::TEXTURE_2D,
-        gl::TEXTURE_MIN_FILTER,
-        gl::LINEAR as gl::GLint,
-    );
-    gl.tex_parameter_i(
-        gl::TEXTURE_2D,
-        gl::TEXTURE_WRAP_S,
-        gl::CLAMP_TO_EDGE as gl::GLint,
-    );
-    gl.tex_parameter_i(
+/// How far the left and right arrow keys seek.
+const SEEK_STEP: Duration = Duration::from_secs(5);
+
+/// What is shown without a video: 75% color bars.
+const COLOR_BARS: VideoInfo = VideoInfo {
+    width: 320,
+    height: 180,
+    format: VideoFormat::I420,
+    frame_rate: (1, 1),
+    color_space: YuvColorSpace::Rec601,
+    color_range: ColorRange::Limited,
+};
+
+/// The external image ids of the comparison's textures start here, after
+/// those of the original.
+const COMPARISON_IMAGE_ID: u64 = 8;
+
+/// A texture of webrender's context holding a plane of the frame shown.
+struct PlaneTexture {
+    texture: gl::GLuint,
+    layout: PlaneLayout,
+}
+
+/// The sized internal format, and the format and type of the data of a
+/// plane.
+fn gl_formats(format: ImageFormat) -> (gl::GLenum, gl::GLenum, gl::GLenum) {
+    match format {
+        ImageFormat::RG8 => (gl::RG8, gl::RG, gl::UNSIGNED_BYTE),
+        ImageFormat::R16 => (gl::R16, gl::RED, gl::UNSIGNED_SHORT),
+        ImageFormat::RG16 => (gl::RG16, gl::RG, gl::UNSIGNED_SHORT),
+        ImageFormat::RGBA8 => (gl::RGBA8, gl::RGBA, gl::UNSIGNED_BYTE),
+        _ => (gl::R8, gl::RED, gl::UNSIGNED_BYTE),
+    }
+}
+
+fn create_texture(gl: &dyn gl::Gl, layout: PlaneLayout) -> gl::GLuint {
+    let texture = gl.gen_textures(1)[0];
+    let (internal_format, format, data_type) = gl_formats(layout.format);
+    gl.bind_texture(gl::TEXTURE_2D, texture);
+    for &(parameter, value) in &[
+        (gl::TEXTURE_MAG_FILTER, gl::LINEAR),
+        (gl::TEXTURE_MIN_FILTER, gl::LINEAR),
+        (gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE),
+        (gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE),
+    ] {
+        gl.tex_parameter_i(gl::TEXTURE_2D, parameter, value as gl::GLint);
+    }
+    gl.tex_image_2d(
         gl::TEXTURE_2D,
-        gl::TEXTURE_WRAP_T,
-        gl::CLAMP_TO_EDGE as gl::GLint,
+        0,
+        internal_format as gl::GLint,
+        layout.width,
+        layout.height,
+        0,
+        format,
+        data_type,
+        None,
     );
-    gl.tex_image_2d(
+    gl.bind_texture(gl::TEXTURE_2D, 0);
+    texture
+}
+
+fn upload_plane(gl: &dyn gl::Gl, plane: &PlaneTexture, data: &[u8]) {
+    let (_, format, data_type) = gl_formats(plane.layout.format);
+    // Rows of odd sized planes are not padded.
+    gl.pixel_store_i(gl::UNPACK_ALIGNMENT, 1);
+    gl.bind_texture(gl::TEXTURE_2D, plane.texture);
+    gl.tex_sub_image_2d(
         gl::TEXTURE_2D,
         0,
-        internal as gl::GLint,
-        100,
-        100,
         0,
-        external,
-        gl::UNSIGNED_BYTE,
-        Some(bytes),
+        0,
+        plane.layout.width,
+        plane.layout.height,
+        format,
+        data_type,
+        data,
     );
     gl.bind_texture(gl::TEXTURE_2D, 0);
+    gl.pixel_store_i(gl::UNPACK_ALIGNMENT, 4);
 }
 
-struct YuvImageProvider {
-    texture_ids: Vec<gl::GLuint>,
+/// White, yellow, cyan, green, magenta, red and blue bars in limited range
+/// Rec. 601.
+fn color_bars(info: &VideoInfo) -> VideoFrame {
+    const BARS: [[f32; 3]; 7] = [
+        [0.75, 0.75, 0.75],
+        [0.75, 0.75, 0.0],
+        [0.0, 0.75, 0.75],
+        [0.0, 0.75, 0.0],
+        [0.75, 0.0, 0.75],
+        [0.75, 0.0, 0.0],
+        [0.0, 0.0, 0.75],
+    ];
+    let ycbcr = |x: i32, width: i32| {
+        let [r, g, b] = BARS[(x * 7 / width) as usize];
+        let y = 0.299 * r + 0.587 * g + 0.114 * b;
+        [
+            (16.0 + 219.0 * y).round() as u8,
+            (128.0 + 112.0 * (b - y) / 0.886).round() as u8,
+            (128.0 + 112.0 * (r - y) / 0.701).round() as u8,
+        ]
+    };
+    let planes = info
+        .format
+        .planes(info.width, info.height)
+        .iter()
+        .enumerate()
+        .map(|(index, plane)| {
+            let row: Vec<u8> = (0..plane.width).map(|x| ycbcr(x, plane.width)[index]).collect();
+            row.repeat(plane.height as usize)
+        })
+        .collect();
+    VideoFrame { index: 0, planes }
 }
 
-impl YuvImageProvider {
-    fn new(gl: &dyn gl::Gl) -> Self {
-        let texture_ids = gl.gen_textures(4);
+/// A frame as webrender shows it: the textures holding its planes, their
+/// images and how to turn their samples into colors.
+struct YuvView {
+    format: VideoFormat,
+    color_space: YuvColorSpace,
+    color_range: ColorRange,
+    /// The external image id of the first plane, the others follow.
+    first_image_id: u64,
+    textures: Vec<PlaneTexture>,
+    image_keys: Vec<ImageKey>,
+}
 
-        init_gl_texture(texture_ids[0], gl::RED, gl::RED, &[127; 100 * 100], gl);
-        init_gl_texture(texture_ids[1], gl::RG8, gl::RG, &[0; 100 * 100 * 2], gl);
-        init_gl_texture(texture_ids[2], gl::RED, gl::RED, &[127; 100 * 100], gl);
-        init_gl_texture(texture_ids[3], gl::RED, gl::RED, &[127; 100 * 100], gl);
+impl YuvView {
+    fn new(info: &VideoInfo, format: VideoFormat, first_image_id: u64) -> Self {
+        YuvView {
+            format,
+            color_space: info.color_space,
+            color_range: info.color_range,
+            first_image_id,
+            textures: Vec::new(),
+            image_keys: Vec::new(),
+        }
+    }
 
-        YuvImageProvider { texture_ids }
+    /// Whether the textures hold the planes of the format.
+    fn has_planes(&self, info: &VideoInfo) -> bool {
+        let layouts = self.format.planes(info.width, info.height);
+        self.textures.iter().map(|plane| plane.layout).eq(layouts)
     }
-}
 
-impl ExternalImageHandler for YuvImageProvider {
-    fn lock(
+    /// Replaces the textures and images with ones for the planes of the
+    /// format.
+    fn create_planes(
         &mut self,
-        key: ExternalImageId,
-        _channel_index: u8,
-        _rendering: ImageRendering,
-    ) -> ExternalImage {
-        let id = self.texture_ids[key.0 as usize];
-        ExternalImage {
-            uv: TexelRect::new(0.0, 0.0, 1.0, 1.0),
-            source: ExternalImageSource::NativeTexture(id),
+        images: &ExternalImages,
+        info: &VideoInfo,
+        compositor: &mut Compositor,
+        document_id: DocumentId,
+    ) {
+        let gl = images.gl();
+        let mut txn = Transaction::new();
+        for image_key in self.image_keys.drain(..) {
+            txn.delete_image(image_key);
+        }
+        let layouts = self.format.planes(info.width, info.height);
+        for index in layouts.len()..self.textures.len() {
+            images.remove(ExternalImageId(self.first_image_id + index as u64), 0);
+        }
+        self.textures.clear();
+
+        for (index, layout) in layouts.into_iter().enumerate() {
+            let id = ExternalImageId(self.first_image_id + index as u64);
+            // Frees the texture registered before, if any.
+            let texture = create_texture(&*gl, layout);
+            images.set_texture(id, 0, texture, TextureTarget::Default, Size2D::new(layout.width, layout.height));
+            self.textures.push(PlaneTexture { texture, layout });
+
+            let image_key = compositor.get_webrender_api().generate_image_key();
+            let (descriptor, data) = self.plane_image(index, layout);
+            txn.add_image(image_key, descriptor, data, None);
+            self.image_keys.push(image_key);
+        }
+        compositor.get_webrender_api().send_transaction(document_id, txn);
+    }
+
+    /// The image of the plane `index`, backed by its texture.
+    fn plane_image(&self, index: usize, layout: PlaneLayout) -> (ImageDescriptor, ImageData) {
+        let descriptor = ImageDescriptor::new(layout.width, layout.height, layout.format, ImageDescriptorFlags::IS_OPAQUE);
+        let data = ImageData::External(ExternalImageData {
+            id: ExternalImageId(self.first_image_id + index as u64),
+            channel_index: 0,
+            image_type: ExternalImageType::TextureHandle(TextureTarget::Default),
+        });
+        (descriptor, data)
+    }
+
+    /// Uploads the frame and updates the images, so webrender doesn't keep
+    /// what it cached of the textures before.
+    fn upload(&self, gl: &dyn gl::Gl, frame: &VideoFrame, txn: &mut Transaction) {
+        for (plane, data) in self.textures.iter().zip(&frame.planes) {
+            upload_plane(gl, plane, data);
+        }
+        for (index, (plane, &image_key)) in self.textures.iter().zip(&self.image_keys).enumerate() {
+            let (descriptor, data) = self.plane_image(index, plane.layout);
+            txn.update_image(image_key, descriptor, data, &DirtyRect::All);
         }
     }
-    fn unlock(&mut self, _key: ExternalImageId, _channel_index: u8) {}
+
+    /// Pushes the frame as large as it fits in `bounds`, centered.
+    fn push(&self, builder: &mut DisplayListBuilder, bounds: LayoutRect, info: &VideoInfo, space_and_clip: SpaceAndClipInfo) {
+        let frame_size = LayoutSize::new(info.width as f32, info.height as f32);
+        let scale = (bounds.size.width / frame_size.width).min(bounds.size.height / frame_size.height);
+        let size = frame_size * scale;
+        let origin = LayoutPoint::new(
+            bounds.origin.x + (bounds.size.width - size.width) / 2.0,
+            bounds.origin.y + (bounds.size.height - size.height) / 2.0,
+        );
+        let frame_bounds = LayoutRect::new(origin, size);
+
+        builder.push_yuv_image(
+            &CommonItemProperties::new(frame_bounds, space_and_clip),
+            frame_bounds,
+            self.format.yuv_data(&self.image_keys),
+            self.format.color_depth(),
+            self.color_space,
+            self.color_range,
+            ImageRendering::Auto,
+        );
+    }
+}
+
+/// Plays a video through YUV images backed by external textures, or shows
+/// color bars without one, next to the same frame converted to another
+/// format. Space pauses, the arrow keys seek, `,` and `.` step a frame and
+/// Home goes back to the start. `F` switches the format of the comparison,
+/// `C` its colorspace and `R` its color range.
+pub struct Yuv {
+    video: Option<VideoSource>,
+    info: VideoInfo,
+    images: Option<ExternalImages>,
+    /// The frame as the video has it, on the left.
+    original: YuvView,
+    /// The frame in the format, colorspace and range picked, on the right.
+    comparison: YuvView,
+    playing: bool,
+    position: Duration,
+    /// The frame in the textures.
+    uploaded_frame: Option<usize>,
+}
+
+impl Default for Yuv {
+    // Computed color bars and no text, which is what the reftest renders.
+    fn default() -> Self {
+        Yuv::new(None, COLOR_BARS)
+    }
 }
 
-struct Yuv {
-    texture_id: gl::GLuint,
-    current_value: u8,
+impl Yuv {
+    pub fn with_video(video: VideoSource) -> Self {
+        let info = *video.info();
+        Yuv::new(Some(video), info)
+    }
+
+    /// Draws at the video's frame rate, unless the options already draw
+    /// continuously.
+    pub fn apply_to(&self, options: &mut RunOptions) {
+        if self.video.is_some() && options.loop_mode == LoopMode::OnEvent {
+            options.loop_mode = LoopMode::Continuous { target_fps: Some(self.info.frames_per_second()) };
+        }
+    }
+
+    fn new(video: Option<VideoSource>, info: VideoInfo) -> Self {
+        Yuv {
+            video,
+            info,
+            images: None,
+            original: YuvView::new(&info, info.format, 0),
+            comparison: YuvView::new(&info, VideoFormat::P010, COMPARISON_IMAGE_ID),
+            playing: true,
+            position: Duration::from_secs(0),
+            uploaded_frame: None,
+        }
+    }
+
+    /// The frame at the current position.
+    fn current_frame(&self) -> usize {
+        self.video.as_ref().map_or(0, |video| video.frame_at(self.position))
+    }
+
+    fn seek(&mut self, position: Duration) {
+        if let Some(ref video) = self.video {
+            self.position = position.min(video.duration() - video.info().frame_duration());
+        }
+    }
+
+    /// Uploads the current frame, unless it is in the textures already.
+    fn upload_frame(&mut self, gl: &dyn gl::Gl, compositor: &mut Compositor, document_id: DocumentId) {
+        let index = self.current_frame();
+        if self.uploaded_frame == Some(index) {
+            return;
+        }
+        let frame = match self.video {
+            Some(ref mut video) => match video.read_frame(index) {
+                Ok(frame) => frame,
+                Err(err) => {
+                    println!("{}", err);
+                    return;
+                }
+            },
+            None => color_bars(&self.info),
+        };
+        let mut txn = Transaction::new();
+        self.original.upload(gl, &frame, &mut txn);
+        let converted = frame.convert(self.info.format, self.comparison.format, self.info.width, self.info.height);
+        self.comparison.upload(gl, &converted, &mut txn);
+        compositor.get_webrender_api().send_transaction(document_id, txn);
+        self.uploaded_frame = Some(frame.index);
+    }
+
+    fn print_comparison(&self) {
+        println!(
+            "Comparing with {} {:?} {:?} range",
+            self.comparison.format.name(),
+            self.comparison.color_space,
+            self.comparison.color_range,
+        );
+    }
 }
 
 impl App for Yuv {
@@ -100,137 +353,122 @@ impl App for Yuv {
         let bounds = LayoutRect::new(LayoutPoint::zero(), builder.content_size());
         let space_and_clip = SpaceAndClipInfo::root_scroll(pipeline_id);
 
+        let images = match self.images {
+            Some(ref images) => images.clone(),
+            None => {
+                println!("No video textures, they need the GL context of the event loop's thread");
+                return builder;
+            }
+        };
+        for view in [&mut self.original, &mut self.comparison].iter_mut() {
+            if !view.has_planes(&self.info) {
+                view.create_planes(&images, &self.info, compositor, document_id);
+                self.uploaded_frame = None;
+            }
+        }
+        self.upload_frame(&*images.gl(), compositor, document_id);
+
         builder.push_simple_stacking_context(
             bounds.origin,
             space_and_clip.spatial_id,
             PrimitiveFlags::IS_BACKFACE_VISIBLE,
         );
 
-        let api = compositor.get_webrender_api();
-
-        let yuv_chanel1 = api.generate_image_key();
-        let yuv_chanel2 = api.generate_image_key();
-        let yuv_chanel2_1 = api.generate_image_key();
-        let yuv_chanel3 = api.generate_image_key();
-
-        let mut txn = Transaction::new();
-
-        txn.add_image(
-            yuv_chanel1,
-            ImageDescriptor::new(100, 100, ImageFormat::R8, ImageDescriptorFlags::IS_OPAQUE),
-            ImageData::External(ExternalImageData {
-                id: ExternalImageId(0),
-                channel_index: 0,
-                image_type: ExternalImageType::TextureHandle(TextureTarget::Default),
-            }),
-            None,
-        );
-
-        txn.add_image(
-            yuv_chanel2,
-            ImageDescriptor::new(100, 100, ImageFormat::RG8, ImageDescriptorFlags::IS_OPAQUE),
-            ImageData::External(ExternalImageData {
-                id: ExternalImageId(1),
-                channel_index: 0,
-                image_type: ExternalImageType::TextureHandle(TextureTarget::Default),
-            }),
-            None,
-        );
-
-        txn.add_image(
-            yuv_chanel2_1,
-            ImageDescriptor::new(100, 100, ImageFormat::R8, ImageDescriptorFlags::IS_OPAQUE),
-            ImageData::External(ExternalImageData {
-                id: ExternalImageId(2),
-                channel_index: 0,
-                image_type: ExternalImageType::TextureHandle(TextureTarget::Default),
-            }),
-            None,
-        );
-
-        txn.add_image(
-            yuv_chanel3,
-            ImageDescriptor::new(100, 100, ImageFormat::R8, ImageDescriptorFlags::IS_OPAQUE),
-            ImageData::External(ExternalImageData {
-                id: ExternalImageId(3),
-                channel_index: 0,
-                image_type: ExternalImageType::TextureHandle(TextureTarget::Default),
-            }),
-            None,
-        );
-
-        compositor
-            .get_webrender_api()
-            .send_transaction(document_id, txn);
-
-        let info = CommonItemProperties::new(
-            LayoutRect::new(LayoutPoint::new(100.0, 100.0), LayoutSize::new(100.0, 100.0)),
-            space_and_clip,
-        );
-
-        builder.push_yuv_image(
-            &info,
-            bounds,
-            YuvData::NV12(yuv_chanel1, yuv_chanel2),
-            ColorDepth::Color8,
-            YuvColorSpace::Rec601,
-            ColorRange::Limited,
-            ImageRendering::Auto,
-        );
-
-        let info = CommonItemProperties::new(
-            LayoutRect::new(LayoutPoint::new(300.0, 100.0), LayoutSize::new(100.0, 100.0)),
-            space_and_clip,
-        );
-        builder.push_yuv_image(
-            &info,
-            bounds,
-            YuvData::PlanarYCbCr(yuv_chanel1, yuv_chanel2_1, yuv_chanel3),
-            ColorDepth::Color8,
-            YuvColorSpace::Rec601,
-            ColorRange::Limited,
-            ImageRendering::Auto,
-        );
+        let half_size = LayoutSize::new(bounds.size.width / 2.0, bounds.size.height);
+        let left = LayoutRect::new(bounds.origin, half_size);
+        let right = LayoutRect::new(LayoutPoint::new(bounds.origin.x + half_size.width, bounds.origin.y), half_size);
+        self.original.push(&mut builder, left, &self.info, space_and_clip);
+        self.comparison.push(&mut builder, right, &self.info, space_and_clip);
 
         builder.pop_stacking_context();
 
         builder
     }
 
+    fn on_event(&mut self, event: winit::WindowEvent, _api: &mut RenderApi, _document_id: DocumentId) -> bool {
+        let key = match event {
+            winit::WindowEvent::KeyboardInput {
+                input: winit::KeyboardInput {
+                    state: winit::ElementState::Pressed,
+                    virtual_keycode: Some(key),
+                    ..
+                },
+                ..
+            } => key,
+            _ => return false,
+        };
+        let frame_duration = self.info.frame_duration();
+        let frame_start = self.video.as_ref().map_or(Duration::from_secs(0), |video| {
+            video.frame_time(self.current_frame())
+        });
+        match key {
+            winit::VirtualKeyCode::Space => self.playing = !self.playing,
+            winit::VirtualKeyCode::Left => self.seek(self.position.checked_sub(SEEK_STEP).unwrap_or_default()),
+            winit::VirtualKeyCode::Right => self.seek(self.position + SEEK_STEP),
+            winit::VirtualKeyCode::Comma => {
+                self.playing = false;
+                self.seek(frame_start.checked_sub(frame_duration).unwrap_or_default());
+            }
+            winit::VirtualKeyCode::Period => {
+                self.playing = false;
+                self.seek(frame_start + frame_duration);
+            }
+            winit::VirtualKeyCode::Home => self.seek(Duration::from_secs(0)),
+            winit::VirtualKeyCode::F => {
+                let formats = VideoFormat::ALL;
+                let index = formats.iter().position(|&format| format == self.comparison.format).unwrap_or(0);
+                self.comparison.format = formats[(index + 1) % formats.len()];
+                // Formats of the same planes don't need new textures, but
+                // still another conversion.
+                self.uploaded_frame = None;
+                self.print_comparison();
+            }
+            winit::VirtualKeyCode::C => {
+                self.comparison.color_space = match self.comparison.color_space {
+                    YuvColorSpace::Rec601 => YuvColorSpace::Rec709,
+                    YuvColorSpace::Rec709 => YuvColorSpace::Rec2020,
+                    _ => YuvColorSpace::Rec601,
+                };
+                self.print_comparison();
+            }
+            winit::VirtualKeyCode::R => {
+                self.comparison.color_range = match self.comparison.color_range {
+                    ColorRange::Limited => ColorRange::Full,
+                    ColorRange::Full => ColorRange::Limited,
+                };
+                self.print_comparison();
+            }
+            _ => return false,
+        }
+        true
+    }
+
+    fn tick(&mut self, time: FrameTime, _: &mut RenderApi, _: DocumentId) -> bool {
+        let duration = match self.video {
+            Some(ref video) if self.playing => video.duration(),
+            _ => return false,
+        };
+        // Loops at the end.
+        self.position += time.delta;
+        if self.position >= duration {
+            self.position = Duration::from_secs(0);
+        }
+        self.uploaded_frame != Some(self.current_frame())
+    }
+
     fn get_image_handlers(
         &mut self,
-        gl: &dyn gl::Gl,
+        _gl: &dyn gl::Gl,
+        webrender_surfman: &WebrenderSurfman,
     ) -> (
         Option<Box<dyn ExternalImageHandler>>,
         Option<Box<dyn OutputImageHandler>>,
     ) {
-        let provider = YuvImageProvider::new(gl);
-        self.texture_id = provider.texture_ids[0];
-        (Some(Box::new(provider)), None)
-    }
-
-    fn draw_custom(&mut self, gl: &dyn gl::Gl) {
-        init_gl_texture(
-            self.texture_id,
-            gl::RED,
-            gl::RED,
-            &[self.current_value; 100 * 100],
-            gl,
-        );
-        self.current_value = self.current_value.wrapping_add(1);
+        // The textures are created with the display list, when the format
+        // of the comparison is known.
+        let images = ExternalImages::new(webrender_surfman);
+        let handler = images.handler();
+        self.images = Some(images);
+        (Some(handler), None)
     }
 }
-
-pub fn run() {
-    let mut yuv_app = Yuv {
-        texture_id: 0,
-        current_value: 0,
-    };
-
-    let opts = webrender::RendererOptions {
-        debug_flags: webrender::DebugFlags::NEW_FRAME_INDICATOR
-            | webrender::DebugFlags::NEW_SCENE_INDICATOR,
-        ..Default::default()
-    };
-    crate::app::run(&mut yuv_app, Some(opts));
-}
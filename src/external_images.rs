@@ -0,0 +1,343 @@
+use crate::{shared_surface::SurfaceProducer, webrender_surfman::WebrenderSurfman};
+use euclid::default::Size2D;
+use gleam::gl;
+use std::{cell::RefCell, collections::HashMap, mem, rc::Rc};
+use surfman::{Device, SurfaceTexture};
+use surfman_chains::{SwapChain, SwapChainAPI};
+use webrender::api::{
+    ExternalImage, ExternalImageHandler, ExternalImageId, ExternalImageSource,
+    ImageRendering, TextureTarget,
+    units::TexelRect
+};
+
+/// An external image and the channel of it webrender asks for.
+type Key = (ExternalImageId, u8);
+
+enum Backing {
+    /// A texture of webrender's context, owned by the registry.
+    Texture {
+        texture: gl::GLuint,
+        target: TextureTarget,
+        size: Size2D<i32>,
+    },
+    /// The frames of a `SurfaceProducer`.
+    Surface {
+        swap_chain: SwapChain<Device>,
+        size: Size2D<i32>,
+        /// The latest frame presented, kept until the next one arrives.
+        surface_texture: Option<SurfaceTexture>,
+    },
+    /// Pixels webrender uploads itself.
+    Buffer(Rc<[u8]>),
+}
+
+struct Entry {
+    backing: Backing,
+    locks: u32,
+    /// The filtering last set on the texture.
+    rendering: Option<ImageRendering>,
+    /// Backings replaced while locked, freed by the last unlock.
+    replaced: Vec<Backing>,
+    /// Removed while locked, freed by the last unlock.
+    removed: bool,
+}
+
+struct Registry {
+    gl: Rc<dyn gl::Gl>,
+    webrender_surfman: WebrenderSurfman,
+    entries: HashMap<Key, Entry>,
+}
+
+/// GL textures, frames of surface producers and CPU buffers, registered
+/// under the `ExternalImageId`s and channels of external images. Apps keep a
+/// clone to update the images between frames and return `handler` from
+/// `App::get_image_handlers`.
+///
+/// The registry owns what is registered: replacing or removing an image
+/// frees it, or its last unlock if webrender has it locked. Textures are
+/// deleted in webrender's context, which has to be current then.
+#[derive(Clone)]
+pub struct ExternalImages(Rc<RefCell<Registry>>);
+
+impl ExternalImages {
+    /// `webrender_surfman` is the context webrender renders with.
+    pub fn new(webrender_surfman: &WebrenderSurfman) -> Self {
+        ExternalImages(Rc::new(RefCell::new(Registry {
+            gl: webrender_surfman.gl_bindings(),
+            webrender_surfman: webrender_surfman.clone(),
+            entries: HashMap::new(),
+        })))
+    }
+
+    /// The GL bindings of webrender's context, to create textures with.
+    pub fn gl(&self) -> Rc<dyn gl::Gl> {
+        self.0.borrow().gl.clone()
+    }
+
+    /// The target to give `ExternalImageType::TextureHandle` for the frames
+    /// of surface producers.
+    pub fn surface_texture_target(&self) -> TextureTarget {
+        texture_target(self.0.borrow().webrender_surfman.surface_gl_texture_target())
+    }
+
+    /// Registers `texture`, with its first row at the top, or replaces what
+    /// was registered under the key.
+    pub fn set_texture(
+        &self,
+        id: ExternalImageId,
+        channel_index: u8,
+        texture: gl::GLuint,
+        target: TextureTarget,
+        size: Size2D<i32>,
+    ) {
+        self.set(id, channel_index, Backing::Texture { texture, target, size });
+    }
+
+    /// Registers the frames `producer` presents, or replaces what was
+    /// registered under the key.
+    pub fn set_producer(&self, id: ExternalImageId, channel_index: u8, producer: &SurfaceProducer) {
+        self.set(id, channel_index, Backing::Surface {
+            swap_chain: producer.swap_chain(),
+            size: producer.size(),
+            surface_texture: None,
+        });
+    }
+
+    /// Registers pixels in the format of the image's descriptor, or replaces
+    /// what was registered under the key. Webrender only reads them again
+    /// after `Transaction::update_image`.
+    pub fn set_buffer(&self, id: ExternalImageId, channel_index: u8, data: Vec<u8>) {
+        self.set(id, channel_index, Backing::Buffer(data.into()));
+    }
+
+    fn set(&self, id: ExternalImageId, channel_index: u8, backing: Backing) {
+        let mut registry = self.0.borrow_mut();
+        let key = (id, channel_index);
+        match registry.entries.get_mut(&key) {
+            // Webrender may still render with the old backing, the next lock
+            // gets the new one.
+            Some(entry) if entry.locks > 0 => {
+                let old = mem::replace(&mut entry.backing, backing);
+                entry.replaced.push(old);
+                entry.rendering = None;
+                entry.removed = false;
+            }
+            _ => {
+                let entry = Entry { backing, locks: 0, rendering: None, replaced: Vec::new(), removed: false };
+                if let Some(old) = registry.entries.insert(key, entry) {
+                    registry.free(old.backing);
+                }
+            }
+        }
+    }
+
+    /// Frees the image, once webrender unlocked it.
+    pub fn remove(&self, id: ExternalImageId, channel_index: u8) {
+        let mut registry = self.0.borrow_mut();
+        let key = (id, channel_index);
+        match registry.entries.get_mut(&key) {
+            Some(entry) if entry.locks > 0 => entry.removed = true,
+            Some(_) => {
+                let entry = registry.entries.remove(&key).unwrap();
+                registry.free_entry(entry);
+            }
+            None => {}
+        }
+    }
+
+    /// The handler to install in webrender. Dropping it, which
+    /// `Renderer::deinit` does with the context current, frees all images.
+    pub fn handler(&self) -> Box<dyn ExternalImageHandler> {
+        Box::new(ExternalImagesHandler {
+            registry: self.0.clone(),
+            locked_buffers: HashMap::new(),
+        })
+    }
+}
+
+impl Registry {
+    /// Frees the backing of an entry and the ones it replaced.
+    fn free_entry(&self, entry: Entry) {
+        for backing in entry.replaced {
+            self.free(backing);
+        }
+        self.free(entry.backing);
+    }
+
+    fn free(&self, backing: Backing) {
+        match backing {
+            Backing::Texture { texture, .. } => self.gl.delete_textures(&[texture]),
+            Backing::Surface { swap_chain, surface_texture: Some(surface_texture), .. } => {
+                recycle(&self.webrender_surfman, &swap_chain, surface_texture)
+            }
+            Backing::Surface { .. } | Backing::Buffer(_) => {}
+        }
+    }
+}
+
+fn texture_target(gl_target: u32) -> TextureTarget {
+    match gl_target {
+        gl::TEXTURE_RECTANGLE => TextureTarget::Rect,
+        gl::TEXTURE_EXTERNAL_OES => TextureTarget::External,
+        _ => TextureTarget::Default,
+    }
+}
+
+fn gl_target(target: TextureTarget) -> gl::GLenum {
+    match target {
+        TextureTarget::Default => gl::TEXTURE_2D,
+        TextureTarget::Array => gl::TEXTURE_2D_ARRAY,
+        TextureTarget::Rect => gl::TEXTURE_RECTANGLE,
+        TextureTarget::External => gl::TEXTURE_EXTERNAL_OES,
+    }
+}
+
+/// Rectangle textures are addressed in texels, others in normalized
+/// coordinates. Flipped images have their first row at the bottom.
+fn texel_rect(target: TextureTarget, size: Size2D<i32>, flipped: bool) -> TexelRect {
+    let (width, height) = match target {
+        TextureTarget::Rect => (size.width as f32, size.height as f32),
+        _ => (1.0, 1.0),
+    };
+    if flipped {
+        TexelRect::new(0.0, height, width, 0.0)
+    } else {
+        TexelRect::new(0.0, 0.0, width, height)
+    }
+}
+
+/// Destroys the texture of a surface in webrender's context and returns the
+/// surface to its producer.
+fn recycle(webrender_surfman: &WebrenderSurfman, swap_chain: &SwapChain<Device>, surface_texture: SurfaceTexture) {
+    match webrender_surfman.destroy_surface_texture(surface_texture) {
+        Ok(surface) => swap_chain.recycle_surface(surface),
+        Err((err, _)) => println!("Failed to destroy surface texture: {:?}", err),
+    }
+}
+
+/// Sets the filtering of `texture` for `rendering`.
+fn set_filter(gl: &dyn gl::Gl, target: gl::GLenum, texture: gl::GLuint, rendering: ImageRendering) {
+    let filter = match rendering {
+        ImageRendering::Auto => gl::LINEAR,
+        ImageRendering::CrispEdges | ImageRendering::Pixelated => gl::NEAREST,
+    };
+    gl.bind_texture(target, texture);
+    gl.tex_parameter_i(target, gl::TEXTURE_MIN_FILTER, filter as gl::GLint);
+    gl.tex_parameter_i(target, gl::TEXTURE_MAG_FILTER, filter as gl::GLint);
+    gl.bind_texture(target, 0);
+}
+
+/// The handler webrender owns, sharing the registry with the app.
+struct ExternalImagesHandler {
+    registry: Rc<RefCell<Registry>>,
+    /// The buffers webrender has locked, held here so that they outlive the
+    /// borrow of the registry.
+    locked_buffers: HashMap<Key, Rc<[u8]>>,
+}
+
+impl ExternalImageHandler for ExternalImagesHandler {
+    fn lock(
+        &mut self,
+        key: ExternalImageId,
+        channel_index: u8,
+        rendering: ImageRendering,
+    ) -> ExternalImage {
+        let key = (key, channel_index);
+        let mut registry = self.registry.borrow_mut();
+        let Registry { ref gl, ref webrender_surfman, ref mut entries } = *registry;
+        let entry = match entries.get_mut(&key) {
+            Some(entry) if !entry.removed => entry,
+            _ => {
+                println!("Webrender locked unknown external image {:?}", key);
+                return ExternalImage {
+                    uv: TexelRect::new(0.0, 0.0, 1.0, 1.0),
+                    source: ExternalImageSource::Invalid,
+                };
+            }
+        };
+        entry.locks += 1;
+
+        let (texture, target, uv) = match entry.backing {
+            Backing::Texture { texture, target, size } => (texture, target, texel_rect(target, size, false)),
+            Backing::Surface { ref swap_chain, size, ref mut surface_texture } => {
+                if let Some(surface) = swap_chain.take_surface() {
+                    if let Some(old) = surface_texture.take() {
+                        recycle(webrender_surfman, swap_chain, old);
+                    }
+                    match webrender_surfman.create_surface_texture(surface) {
+                        Ok(new) => *surface_texture = Some(new),
+                        Err((err, surface)) => {
+                            println!("Failed to import shared surface: {:?}", err);
+                            swap_chain.recycle_surface(surface);
+                        }
+                    }
+                    // A new texture, its filtering has to be set again.
+                    entry.rendering = None;
+                }
+                let target = texture_target(webrender_surfman.surface_gl_texture_target());
+                let texture = surface_texture
+                    .as_ref()
+                    .map_or(0, |surface_texture| webrender_surfman.surface_texture_object(surface_texture));
+                // GL rows start at the bottom.
+                (texture, target, texel_rect(target, size, true))
+            }
+            Backing::Buffer(ref data) => {
+                let data = self.locked_buffers.entry(key).or_insert_with(|| data.clone());
+                return ExternalImage {
+                    uv: TexelRect::new(0.0, 0.0, 1.0, 1.0),
+                    source: ExternalImageSource::RawData(&data[..]),
+                };
+            }
+        };
+
+        if texture == 0 {
+            return ExternalImage { uv, source: ExternalImageSource::Invalid };
+        }
+        if entry.rendering != Some(rendering) {
+            set_filter(&**gl, gl_target(target), texture, rendering);
+            entry.rendering = Some(rendering);
+        }
+        ExternalImage { uv, source: ExternalImageSource::NativeTexture(texture) }
+    }
+
+    fn unlock(&mut self, key: ExternalImageId, channel_index: u8) {
+        let key = (key, channel_index);
+        self.locked_buffers.remove(&key);
+
+        let mut registry = self.registry.borrow_mut();
+        let entry = match registry.entries.get_mut(&key) {
+            Some(entry) if entry.locks > 0 => entry,
+            _ => {
+                println!("Webrender unlocked {:?} without locking it", key);
+                return;
+            }
+        };
+        entry.locks -= 1;
+        if entry.locks > 0 {
+            return;
+        }
+        if entry.removed {
+            let entry = registry.entries.remove(&key).unwrap();
+            registry.free_entry(entry);
+        } else if !entry.replaced.is_empty() {
+            let replaced = mem::replace(&mut entry.replaced, Vec::new());
+            for backing in replaced {
+                registry.free(backing);
+            }
+        }
+    }
+}
+
+impl Drop for ExternalImagesHandler {
+    // Dropped by `Renderer::deinit`, with webrender's context current.
+    fn drop(&mut self) {
+        let mut registry = self.registry.borrow_mut();
+        let entries: Vec<Entry> = registry.entries.drain().map(|(_, entry)| entry).collect();
+        for entry in entries {
+            if entry.locks > 0 {
+                println!("Freeing an external image webrender didn't unlock");
+            }
+            registry.free_entry(entry);
+        }
+    }
+}
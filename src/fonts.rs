@@ -0,0 +1,215 @@
+use crate::text::Font;
+use font_kit::{
+    family_name::FamilyName,
+    handle::Handle,
+    properties::{Properties, Style, Weight},
+    source::SystemSource
+};
+use std::{collections::HashMap, fmt, fs, io, path::PathBuf, rc::Rc};
+use webrender::api::{FontInstanceKey, FontKey, FontVariation, RenderApi, Transaction};
+
+/// A font as requested by an app.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum FontSource {
+    /// A font file, the first font of it for collections.
+    Path(PathBuf),
+    /// The best match of the system's fonts, through fontconfig on Linux.
+    System(FontDescriptor),
+}
+
+/// Family, weight and style of a system font.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct FontDescriptor {
+    /// A family name, or one of the generic `serif`, `sans-serif`,
+    /// `monospace`, `cursive` and `fantasy`.
+    pub family: String,
+    /// From 100 to 900, 400 being regular and 700 bold.
+    pub weight: u16,
+    pub italic: bool,
+}
+
+impl FontDescriptor {
+    pub fn new(family: &str) -> Self {
+        FontDescriptor {
+            family: family.to_owned(),
+            weight: 400,
+            italic: false,
+        }
+    }
+
+    pub fn weight(mut self, weight: u16) -> Self {
+        self.weight = weight;
+        self
+    }
+
+    pub fn bold(self) -> Self {
+        self.weight(700)
+    }
+
+    pub fn italic(mut self) -> Self {
+        self.italic = true;
+        self
+    }
+
+    fn family_name(&self) -> FamilyName {
+        match self.family.as_str() {
+            "serif" => FamilyName::Serif,
+            "sans-serif" => FamilyName::SansSerif,
+            "monospace" => FamilyName::Monospace,
+            "cursive" => FamilyName::Cursive,
+            "fantasy" => FamilyName::Fantasy,
+            family => FamilyName::Title(family.to_owned()),
+        }
+    }
+}
+
+impl From<PathBuf> for FontSource {
+    fn from(path: PathBuf) -> Self {
+        FontSource::Path(path)
+    }
+}
+
+impl From<FontDescriptor> for FontSource {
+    fn from(descriptor: FontDescriptor) -> Self {
+        FontSource::System(descriptor)
+    }
+}
+
+#[derive(Debug)]
+pub enum FontError {
+    Io(PathBuf, io::Error),
+    /// No system font matches the descriptor.
+    NotFound(FontDescriptor),
+    /// The data is not a font that can be parsed.
+    Invalid(FontSource),
+}
+
+impl fmt::Display for FontError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FontError::Io(path, err) => write!(f, "Failed to read font {:?}: {}", path, err),
+            FontError::NotFound(descriptor) => write!(f, "No system font matches {:?}", descriptor),
+            FontError::Invalid(source) => write!(f, "Failed to parse font {:?}", source),
+        }
+    }
+}
+
+impl std::error::Error for FontError {}
+
+struct LoadedFont {
+    key: FontKey,
+    font: Rc<Font>,
+}
+
+struct LoadedInstance {
+    font: Rc<Font>,
+    size: f32,
+}
+
+/// Loads fonts into webrender once per source, and creates instances once
+/// per font, size and variations.
+#[derive(Default)]
+pub struct FontManager {
+    fonts: HashMap<FontSource, LoadedFont>,
+    instance_keys: HashMap<(FontKey, u32, Vec<(u32, u32)>), FontInstanceKey>,
+    instances: HashMap<FontInstanceKey, LoadedInstance>,
+}
+
+impl FontManager {
+    /// Returns the key of a loaded font, adding it to `txn` on first use.
+    pub fn font(
+        &mut self,
+        api: &RenderApi,
+        txn: &mut Transaction,
+        source: &FontSource,
+    ) -> Result<FontKey, FontError> {
+        if let Some(font) = self.fonts.get(source) {
+            return Ok(font.key);
+        }
+
+        let (bytes, index) = load(source)?;
+        let font = Font::from_bytes(bytes.clone(), index)
+            .ok_or_else(|| FontError::Invalid(source.clone()))?;
+
+        let key = api.generate_font_key();
+        txn.add_raw_font(key, bytes, index);
+        println!("Loaded font {:?} as {:?}", source, key);
+
+        self.fonts.insert(source.clone(), LoadedFont { key, font: Rc::new(font) });
+        Ok(key)
+    }
+
+    /// Returns an instance of a font at `size` with `variations`, creating it
+    /// and loading the font on first use.
+    pub fn instance(
+        &mut self,
+        api: &RenderApi,
+        txn: &mut Transaction,
+        source: &FontSource,
+        size: f32,
+        variations: &[FontVariation],
+    ) -> Result<FontInstanceKey, FontError> {
+        let font_key = self.font(api, txn, source)?;
+        let variation_bits = variations
+            .iter()
+            .map(|variation| (variation.tag, variation.value.to_bits()))
+            .collect();
+        let cache_key = (font_key, size.to_bits(), variation_bits);
+        if let Some(instance_key) = self.instance_keys.get(&cache_key) {
+            return Ok(*instance_key);
+        }
+
+        let instance_key = api.generate_font_instance_key();
+        txn.add_font_instance(instance_key, font_key, size, None, None, variations.to_vec());
+
+        let font = self.fonts[source].font.clone();
+        self.instance_keys.insert(cache_key, instance_key);
+        self.instances.insert(instance_key, LoadedInstance { font, size });
+        Ok(instance_key)
+    }
+
+    /// The parsed font and the size of an instance, to lay out text with.
+    pub fn font_instance(&self, instance_key: FontInstanceKey) -> Option<(Rc<Font>, f32)> {
+        self.instances
+            .get(&instance_key)
+            .map(|instance| (instance.font.clone(), instance.size))
+    }
+
+    /// Deletes every font and instance, e.g. when font files changed on disk.
+    pub fn clear(&mut self, txn: &mut Transaction) {
+        for (instance_key, _) in self.instances.drain() {
+            txn.delete_font_instance(instance_key);
+        }
+        for (_, font) in self.fonts.drain() {
+            txn.delete_font(font.key);
+        }
+        self.instance_keys.clear();
+    }
+}
+
+/// Reads the data of a font and its index in the file.
+fn load(source: &FontSource) -> Result<(Vec<u8>, u32), FontError> {
+    match source {
+        FontSource::Path(path) => {
+            let bytes = fs::read(path).map_err(|err| FontError::Io(path.clone(), err))?;
+            Ok((bytes, 0))
+        }
+        FontSource::System(descriptor) => {
+            let mut properties = Properties::new();
+            properties.weight(Weight(descriptor.weight as f32));
+            if descriptor.italic {
+                properties.style(Style::Italic);
+            }
+            let handle = SystemSource::new()
+                .select_best_match(&[descriptor.family_name()], &properties)
+                .map_err(|_| FontError::NotFound(descriptor.clone()))?;
+            match handle {
+                Handle::Path { path, font_index } => {
+                    let bytes = fs::read(&path).map_err(|err| FontError::Io(path.clone(), err))?;
+                    Ok((bytes, font_index))
+                }
+                Handle::Memory { bytes, font_index } => Ok(((*bytes).clone(), font_index)),
+            }
+        }
+    }
+}
@@ -0,0 +1,112 @@
+use crate::{
+    error::Error,
+    webrender_surfman::{BuildError, WebrenderSurfman, WebrenderSurfmanBuilder},
+    window::{DeviceIndependentPixel, EmbedderCoordinates, WindowMethods}
+};
+use euclid::{Point2D, Scale, Size2D};
+use std::{sync::{Arc, Condvar, Mutex}, time::Duration};
+use surfman::{Connection, SurfaceType};
+use webrender::api::{
+    DocumentId, RenderNotifier,
+    units::{DeviceIntRect, DevicePixel}
+};
+
+/// Stands in for a `Window` when rendering offscreen: the surfman context
+/// renders into a generic surface backed by a `SwapChain`.
+pub struct HeadlessWindow {
+    size: Size2D<u32, DeviceIndependentPixel>,
+    hidpi_factor: Scale<f32, DeviceIndependentPixel, DevicePixel>,
+}
+
+impl HeadlessWindow {
+    pub fn new(
+        (width, height): (u32, u32),
+        hidpi_factor: f32,
+    ) -> Self {
+        HeadlessWindow {
+            size: Size2D::new(width, height),
+            hidpi_factor: Scale::new(hidpi_factor),
+        }
+    }
+}
+
+impl WindowMethods for HeadlessWindow {
+    fn create_webrender_surfman(&self, surfman: &WebrenderSurfmanBuilder) -> Result<WebrenderSurfman, BuildError> {
+        let framebuffer = (self.size.to_f32() * self.hidpi_factor).to_i32();
+        let connection = Connection::new().map_err(BuildError::Connection)?;
+        let webrender_surfman = surfman.build(&connection, || {
+            Ok(SurfaceType::Generic { size: framebuffer.to_untyped() })
+        })?;
+        println!("Created headless surface {}x{}", framebuffer.width, framebuffer.height);
+        Ok(webrender_surfman)
+    }
+
+    fn get_coordinates(&self) -> Result<EmbedderCoordinates, Error> {
+        let framebuffer = (self.size.to_f32() * self.hidpi_factor).to_i32();
+        let viewport = DeviceIntRect::new(Point2D::zero(), framebuffer);
+        let layout = framebuffer.to_f32() / Scale::new(self.hidpi_factor.get());
+        Ok(EmbedderCoordinates {
+            viewport,
+            framebuffer,
+            window: (framebuffer, Point2D::zero()),
+            screen: framebuffer,
+            hidpi_factor: self.hidpi_factor,
+            layout
+        })
+    }
+}
+
+/// A `RenderNotifier` for runs without an event loop, the runner blocks on
+/// `FrameReady::wait` instead of being woken up.
+pub struct FrameNotifier {
+    frame_ready: Arc<(Mutex<u64>, Condvar)>,
+}
+
+/// Counts the frames webrender produced.
+#[derive(Clone)]
+pub struct FrameReady(Arc<(Mutex<u64>, Condvar)>);
+
+impl FrameNotifier {
+    pub fn new() -> (FrameNotifier, FrameReady) {
+        let frame_ready = Arc::new((Mutex::new(0), Condvar::new()));
+        (FrameNotifier { frame_ready: frame_ready.clone() }, FrameReady(frame_ready))
+    }
+}
+
+impl RenderNotifier for FrameNotifier {
+    fn clone(&self) -> Box<dyn RenderNotifier> {
+        Box::new(FrameNotifier {
+            frame_ready: self.frame_ready.clone(),
+        })
+    }
+
+    fn wake_up(&self) {}
+
+    fn new_frame_ready(&self,
+                       _: DocumentId,
+                       _scrolled: bool,
+                       _composite_needed: bool,
+                       _render_time: Option<u64>) {
+        let (ref count, ref condvar) = *self.frame_ready;
+        *count.lock().unwrap() += 1;
+        condvar.notify_all();
+    }
+}
+
+impl FrameReady {
+    /// The number of frames produced so far.
+    pub fn count(&self) -> u64 {
+        *(self.0).0.lock().unwrap()
+    }
+
+    /// Blocks until webrender produced a frame after the first `count`
+    /// ones, returns false if none arrived within `timeout`.
+    pub fn wait_past(&self, count: u64, timeout: Duration) -> bool {
+        let (ref frames, ref condvar) = *self.0;
+        let frames = frames.lock().unwrap();
+        let (frames, _) = condvar
+            .wait_timeout_while(frames, timeout, |frames| *frames <= count)
+            .unwrap();
+        *frames > count
+    }
+}
@@ -0,0 +1,303 @@
+use std::{fmt, fs, io, path::{Path, PathBuf}};
+use webrender::api::{ImageData, ImageDescriptor, ImageDescriptorFlags, ImageFormat};
+
+/// A decoded image in the format webrender takes it in, with premultiplied
+/// alpha.
+#[derive(Clone)]
+pub struct Image {
+    pub descriptor: ImageDescriptor,
+    pub pixels: Vec<u8>,
+}
+
+#[derive(Debug)]
+pub enum ImageError {
+    Io(PathBuf, io::Error),
+    /// The data is not a PNG, JPEG or GIF file.
+    UnknownFormat,
+    Decode(String),
+}
+
+impl fmt::Display for ImageError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ImageError::Io(path, err) => write!(f, "Failed to read image {:?}: {}", path, err),
+            ImageError::UnknownFormat => write!(f, "Not a PNG, JPEG or GIF image"),
+            ImageError::Decode(err) => write!(f, "Failed to decode image: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for ImageError {}
+
+fn decode_error<E: fmt::Display>(err: E) -> ImageError {
+    ImageError::Decode(err.to_string())
+}
+
+impl Image {
+    pub fn open(path: &Path) -> Result<Image, ImageError> {
+        let bytes = fs::read(path).map_err(|err| ImageError::Io(path.to_owned(), err))?;
+        Image::decode(&bytes)
+    }
+
+    /// Decodes a PNG, JPEG or GIF file, the first frame of animated ones.
+    /// Grayscale images become `R8` or `R16`, 16 bit color ones `RGBAF32`
+    /// and all others `BGRA8`.
+    pub fn decode(bytes: &[u8]) -> Result<Image, ImageError> {
+        if bytes.starts_with(b"\x89PNG\r\n\x1a\n") {
+            decode_png(bytes)
+        } else if bytes.starts_with(&[0xFF, 0xD8, 0xFF]) {
+            decode_jpeg(bytes)
+        } else if bytes.starts_with(b"GIF8") {
+            decode_gif(bytes)
+        } else {
+            Err(ImageError::UnknownFormat)
+        }
+    }
+
+    fn new(width: usize, height: usize, format: ImageFormat, opaque: bool, pixels: Vec<u8>) -> Image {
+        let flags = if opaque { ImageDescriptorFlags::IS_OPAQUE } else { ImageDescriptorFlags::empty() };
+        Image {
+            descriptor: ImageDescriptor::new(width as i32, height as i32, format, flags),
+            pixels,
+        }
+    }
+
+    /// An 8 bit image from straight alpha RGBA texels.
+    fn from_rgba8<I>(width: usize, height: usize, texels: I) -> Image
+    where
+        I: Iterator<Item = [u8; 4]>,
+    {
+        let mut opaque = true;
+        let mut pixels = Vec::with_capacity(width * height * 4);
+        for [r, g, b, a] in texels {
+            let premultiply = |value: u8| ((value as u32 * a as u32 + 127) / 255) as u8;
+            pixels.extend_from_slice(&[premultiply(b), premultiply(g), premultiply(r), a]);
+            opaque &= a == 255;
+        }
+        Image::new(width, height, ImageFormat::BGRA8, opaque, pixels)
+    }
+
+    /// A float image from straight alpha RGBA texels of 16 bit samples.
+    fn from_rgba16<I>(width: usize, height: usize, texels: I) -> Image
+    where
+        I: Iterator<Item = [u16; 4]>,
+    {
+        let mut opaque = true;
+        let mut pixels = Vec::with_capacity(width * height * 16);
+        for [r, g, b, a] in texels {
+            let alpha = a as f32 / 65535.0;
+            for value in &[r as f32 / 65535.0 * alpha, g as f32 / 65535.0 * alpha, b as f32 / 65535.0 * alpha, alpha] {
+                pixels.extend_from_slice(&value.to_ne_bytes());
+            }
+            opaque &= a == 65535;
+        }
+        Image::new(width, height, ImageFormat::RGBAF32, opaque, pixels)
+    }
+
+    /// The descriptor and data to give `Transaction::add_image`.
+    pub fn into_data(self) -> (ImageDescriptor, ImageData) {
+        (self.descriptor, ImageData::new(self.pixels))
+    }
+}
+
+/// Spreads the samples of gray, gray and alpha, RGB or RGBA texels to RGBA.
+fn rgba<T: Copy>(samples: &[T], channels: usize, opaque: T) -> impl Iterator<Item = [T; 4]> + '_ {
+    samples.chunks(channels).map(move |texel| match *texel {
+        [gray] => [gray, gray, gray, opaque],
+        [gray, alpha] => [gray, gray, gray, alpha],
+        [r, g, b] => [r, g, b, opaque],
+        [r, g, b, a, ..] => [r, g, b, a],
+        [] => [opaque; 4],
+    })
+}
+
+fn decode_png(bytes: &[u8]) -> Result<Image, ImageError> {
+    let mut decoder = png::Decoder::new(bytes);
+    // Palettes, transparency chunks and sub-byte depths become plain
+    // gray, gray and alpha, RGB or RGBA samples.
+    decoder.set_transformations(png::Transformations::EXPAND);
+    let (info, mut reader) = decoder.read_info().map_err(decode_error)?;
+    let mut buffer = vec![0; reader.output_buffer_size()];
+    reader.next_frame(&mut buffer).map_err(decode_error)?;
+
+    let (width, height) = (info.width as usize, info.height as usize);
+    let (color_type, bit_depth) = reader.output_color_type();
+    let channels = color_type.samples();
+    let image = match (color_type, bit_depth) {
+        (png::ColorType::Grayscale, png::BitDepth::Eight) => {
+            Image::new(width, height, ImageFormat::R8, true, buffer)
+        }
+        (_, png::BitDepth::Sixteen) => {
+            let samples: Vec<u16> = buffer
+                .chunks(2)
+                .map(|sample| u16::from_be_bytes([sample[0], sample[1]]))
+                .collect();
+            if color_type == png::ColorType::Grayscale {
+                let pixels = samples.iter().flat_map(|sample| sample.to_ne_bytes().to_vec()).collect();
+                Image::new(width, height, ImageFormat::R16, true, pixels)
+            } else {
+                Image::from_rgba16(width, height, rgba(&samples, channels, u16::MAX))
+            }
+        }
+        _ => Image::from_rgba8(width, height, rgba(&buffer, channels, u8::MAX)),
+    };
+    Ok(image)
+}
+
+fn decode_jpeg(bytes: &[u8]) -> Result<Image, ImageError> {
+    let mut decoder = jpeg_decoder::Decoder::new(bytes);
+    let pixels = decoder.decode().map_err(decode_error)?;
+    let info = decoder.info().ok_or_else(|| decode_error("missing frame header"))?;
+
+    let (width, height) = (info.width as usize, info.height as usize);
+    let image = match info.pixel_format {
+        jpeg_decoder::PixelFormat::L8 => Image::new(width, height, ImageFormat::R8, true, pixels),
+        jpeg_decoder::PixelFormat::RGB24 => Image::from_rgba8(width, height, rgba(&pixels, 3, 255)),
+        jpeg_decoder::PixelFormat::CMYK32 => {
+            let texels = pixels.chunks(4).map(|cmyk| {
+                let key = 255 - cmyk[3] as u32;
+                let channel = |value: u8| ((255 - value as u32) * key / 255) as u8;
+                [channel(cmyk[0]), channel(cmyk[1]), channel(cmyk[2]), 255]
+            });
+            Image::from_rgba8(width, height, texels)
+        }
+    };
+    Ok(image)
+}
+
+fn decode_gif(bytes: &[u8]) -> Result<Image, ImageError> {
+    use gif::SetParameter;
+
+    let mut decoder = gif::Decoder::new(bytes);
+    decoder.set(gif::ColorOutput::RGBA);
+    let mut reader = decoder.read_info().map_err(decode_error)?;
+    let (width, height) = (reader.width() as usize, reader.height() as usize);
+    let frame = reader
+        .read_next_frame()
+        .map_err(decode_error)?
+        .ok_or_else(|| decode_error("no frames"))?;
+    if frame.width == 0 || frame.height == 0 {
+        return Err(decode_error("empty frame"));
+    }
+
+    // Frames may cover part of the canvas only, the rest is transparent.
+    let mut canvas = vec![0; width * height * 4];
+    let (left, top) = (frame.left as usize, frame.top as usize);
+    for (row, line) in frame.buffer.chunks(frame.width as usize * 4).enumerate() {
+        let y = top + row;
+        if y >= height || left >= width {
+            break;
+        }
+        let length = line.len().min((width - left) * 4);
+        let start = (y * width + left) * 4;
+        canvas[start..start + length].copy_from_slice(&line[..length]);
+    }
+
+    Ok(Image::from_rgba8(width, height, rgba(&canvas, 4, 255)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn open(name: &str) -> Image {
+        Image::open(&Path::new("res/images").join(name)).unwrap()
+    }
+
+    fn encode_png(width: u32, height: u32, color_type: png::ColorType, bit_depth: png::BitDepth, data: &[u8]) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        {
+            let mut encoder = png::Encoder::new(&mut bytes, width, height);
+            encoder.set_color(color_type);
+            encoder.set_depth(bit_depth);
+            encoder.write_header().unwrap().write_image_data(data).unwrap();
+        }
+        bytes
+    }
+
+    fn pixel(image: &Image, x: i32, y: i32) -> &[u8] {
+        let start = ((y * image.descriptor.size.width + x) * 4) as usize;
+        &image.pixels[start..start + 4]
+    }
+
+    #[test]
+    fn decodes_rgba_pngs_to_bgra8() {
+        let image = open("checker.png");
+        assert_eq!(image.descriptor.format, ImageFormat::BGRA8);
+        assert_eq!((image.descriptor.size.width, image.descriptor.size.height), (32, 32));
+        assert_eq!(image.pixels.len(), 32 * 32 * 4);
+        // Every texel is opaque, although the PNG has an alpha channel.
+        assert!(image.descriptor.is_opaque());
+        assert_eq!(pixel(&image, 0, 0), &[90, 70, 60, 255]);
+    }
+
+    #[test]
+    fn decodes_jpegs_to_opaque_bgra8() {
+        let image = open("gradient.jpg");
+        assert_eq!(image.descriptor.format, ImageFormat::BGRA8);
+        assert_eq!((image.descriptor.size.width, image.descriptor.size.height), (160, 120));
+        assert!(image.descriptor.is_opaque());
+        assert_eq!(pixel(&image, 0, 0), &[160, 0, 0, 255]);
+    }
+
+    #[test]
+    fn decodes_gifs_with_transparent_texels() {
+        let image = open("disc.gif");
+        assert_eq!(image.descriptor.format, ImageFormat::BGRA8);
+        assert_eq!((image.descriptor.size.width, image.descriptor.size.height), (64, 64));
+        assert!(!image.descriptor.is_opaque());
+        // Outside the disc, premultiplied to transparent black.
+        assert_eq!(pixel(&image, 0, 0), &[0, 0, 0, 0]);
+        assert_eq!(pixel(&image, 32, 32)[3], 255);
+    }
+
+    #[test]
+    fn premultiplies_alpha() {
+        let bytes = encode_png(2, 1, png::ColorType::RGBA, png::BitDepth::Eight, &[200, 100, 50, 128, 10, 20, 30, 0]);
+        let image = Image::decode(&bytes).unwrap();
+        assert_eq!(image.descriptor.format, ImageFormat::BGRA8);
+        assert!(!image.descriptor.is_opaque());
+        assert_eq!(image.pixels, vec![25, 50, 100, 128, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn decodes_grayscale_pngs_to_r8_and_r16() {
+        let bytes = encode_png(2, 1, png::ColorType::Grayscale, png::BitDepth::Eight, &[0, 200]);
+        let image = Image::decode(&bytes).unwrap();
+        assert_eq!(image.descriptor.format, ImageFormat::R8);
+        assert!(image.descriptor.is_opaque());
+        assert_eq!(image.pixels, vec![0, 200]);
+
+        let bytes = encode_png(1, 1, png::ColorType::Grayscale, png::BitDepth::Sixteen, &[0x12, 0x34]);
+        let image = Image::decode(&bytes).unwrap();
+        assert_eq!(image.descriptor.format, ImageFormat::R16);
+        assert_eq!(image.pixels, 0x1234u16.to_ne_bytes().to_vec());
+    }
+
+    #[test]
+    fn decodes_16_bit_color_pngs_to_float() {
+        let bytes = encode_png(1, 1, png::ColorType::RGBA, png::BitDepth::Sixteen, &[0xFF, 0xFF, 0, 0, 0, 0, 0x80, 0x00]);
+        let image = Image::decode(&bytes).unwrap();
+        assert_eq!(image.descriptor.format, ImageFormat::RGBAF32);
+        assert!(!image.descriptor.is_opaque());
+        let samples: Vec<f32> = image.pixels
+            .chunks(4)
+            .map(|bytes| f32::from_ne_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+            .collect();
+        let alpha = 0x8000 as f32 / 65535.0;
+        assert_eq!(samples, vec![alpha, 0.0, 0.0, alpha]);
+    }
+
+    #[test]
+    fn rejects_unknown_and_empty_images() {
+        assert!(matches!(Image::decode(b"BM not an image"), Err(ImageError::UnknownFormat)));
+
+        let mut bytes = Vec::new();
+        {
+            let mut encoder = gif::Encoder::new(&mut bytes, 1, 1, &[0, 0, 0, 255, 255, 255]).unwrap();
+            let frame = gif::Frame { width: 0, height: 0, ..gif::Frame::default() };
+            encoder.write_frame(&frame).unwrap();
+        }
+        assert!(matches!(Image::decode(&bytes), Err(ImageError::Decode(_))));
+    }
+}
@@ -1,12 +1,106 @@
 mod compositor;
+mod error;
 mod examples;
+mod external_images;
+mod fonts;
+mod images;
 mod webrender_surfman;
 mod window;
 mod app;
+mod cli;
+mod headless;
+mod reftest;
+mod render_thread;
+mod scene;
+mod shared_surface;
+mod text;
+mod vector_images;
+mod video;
+mod watcher;
+
+use std::{env, process};
+use crate::{
+    app::RunOptions,
+    cli::Command,
+    compositor::Capture,
+    error::Error,
+    examples::yuv::Yuv,
+    scene::SceneApp,
+    video::VideoSource
+};
 
 fn main() {
-    // examples::basic::run();
-    // examples::yuv::run();
-    // examples::scrolling::run();
-    examples::animation::run();
+    env_logger::init();
+
+    let command = match cli::parse(env::args().skip(1)) {
+        Ok(command) => command,
+        Err(message) => {
+            eprintln!("{}\n\n{}", message, cli::USAGE);
+            process::exit(2);
+        }
+    };
+
+    if let Err(err) = run(command) {
+        eprintln!("{}", err);
+        process::exit(match err {
+            Error::UnknownExample(_) => 2,
+            _ => 1,
+        });
+    }
+}
+
+fn run(command: Command) -> Result<(), Error> {
+    match command {
+        Command::Help => println!("{}", cli::USAGE),
+        Command::List => {
+            for example in examples::EXAMPLES {
+                println!("{:<12} {}", example.name, example.description);
+            }
+        }
+        Command::Reftest(options) => {
+            if !reftest::run(&options) {
+                return Err(Error::ReftestFailed);
+            }
+        }
+        Command::Run { example, options } => match examples::find(&example) {
+            Some(example) => match options.headless {
+                Some(frames) => write_capture((example.render)(&options, frames)?, frames, &options)?,
+                None => (example.run)(&options)?,
+            },
+            None => return Err(Error::UnknownExample(example)),
+        },
+        Command::Scene { path, mut options } => {
+            let mut scene = SceneApp::load(&path)?;
+            scene.apply_to(&mut options);
+            match options.headless {
+                Some(frames) => write_capture(app::run_headless(&mut scene, &options, frames)?, frames, &options)?,
+                None => app::run(&mut scene, &options)?,
+            }
+        }
+        Command::Video { path, raw, mut options } => {
+            let video = match raw {
+                Some(layout) => VideoSource::open_raw(&path, layout)?,
+                None => VideoSource::open(&path)?,
+            };
+            let mut yuv = Yuv::with_video(video);
+            yuv.apply_to(&mut options);
+            match options.headless {
+                Some(frames) => write_capture(app::run_headless(&mut yuv, &options, frames)?, frames, &options)?,
+                None => app::run(&mut yuv, &options)?,
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Reports a headless run and writes its capture to `--output`.
+fn write_capture(capture: Capture, frames: usize, options: &RunOptions) -> Result<(), Error> {
+    println!(
+        "Rendered {} frame(s) of {}x{} pixels",
+        frames, capture.size.width, capture.size.height
+    );
+    if let Some(ref output) = options.output {
+        capture.write_png(output).map_err(|err| Error::Output(output.clone(), err))?;
+    }
+    Ok(())
 }
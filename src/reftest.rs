@@ -0,0 +1,316 @@
+use crate::{
+    app::RunOptions,
+    compositor::Capture,
+    examples::{self, Example}
+};
+use std::{fs::{self, File}, io, path::{Path, PathBuf}};
+use webrender::api::units::DeviceIntSize;
+
+/// Every reference is rendered at this size and device pixel ratio so that
+/// the images do not depend on the machine running the tests.
+const REFTEST_SIZE: (u32, u32) = (800, 600);
+const REFTEST_DEVICE_PIXEL_RATIO: f32 = 1.0;
+/// Frames rendered before capturing, some examples change their content per frame.
+const REFTEST_FRAMES: usize = 1;
+/// The examples with references in `res/reftests`, tested when none are
+/// given. Their content must only depend on the frame count: no system
+/// fonts, no wall clock time. Each of them notes what keeps it that way.
+const REFTEST_EXAMPLES: &[&str] = &["animation", "basic", "scrolling", "yuv"];
+
+pub struct ReftestOptions {
+    /// Examples to test, `REFTEST_EXAMPLES` when empty.
+    pub examples: Vec<String>,
+    /// Directory holding the `<example>.png` references.
+    pub reference_dir: PathBuf,
+    /// Directory receiving the `<example>-diff.png` images of failures.
+    pub output_dir: PathBuf,
+    /// Largest difference of a channel for two pixels to be considered equal.
+    pub tolerance: u8,
+    /// Number of differing pixels a test tolerates before failing.
+    pub max_differing_pixels: usize,
+    /// Overwrite the references with the rendered images instead of comparing.
+    pub bless: bool,
+}
+
+impl Default for ReftestOptions {
+    fn default() -> Self {
+        ReftestOptions {
+            examples: Vec::new(),
+            reference_dir: PathBuf::from("res/reftests"),
+            output_dir: PathBuf::from("target/reftests"),
+            tolerance: 0,
+            max_differing_pixels: 0,
+            bless: false,
+        }
+    }
+}
+
+struct Comparison {
+    differing_pixels: usize,
+    max_difference: u8,
+    diff: Capture,
+}
+
+impl Comparison {
+    fn passes(&self, max_differing_pixels: usize) -> bool {
+        self.differing_pixels <= max_differing_pixels
+    }
+}
+
+/// Renders the selected examples offscreen and compares them against their
+/// references. Returns whether all of them passed.
+pub fn run(options: &ReftestOptions) -> bool {
+    let names: Vec<&str> = if options.examples.is_empty() {
+        REFTEST_EXAMPLES.to_vec()
+    } else {
+        options.examples.iter().map(String::as_str).collect()
+    };
+    let mut selected: Vec<&Example> = Vec::new();
+    for name in names {
+        match examples::find(name) {
+            Some(example) => selected.push(example),
+            None => {
+                println!("REFTEST ERROR unknown example '{}'", name);
+                return false;
+            }
+        }
+    }
+
+    let run_options = RunOptions {
+        size: Some(REFTEST_SIZE),
+        device_pixel_ratio: Some(REFTEST_DEVICE_PIXEL_RATIO),
+        ..RunOptions::default()
+    };
+
+    let mut failures = 0;
+    for example in &selected {
+        let capture = match (example.render)(&run_options, REFTEST_FRAMES) {
+            Ok(capture) => capture,
+            Err(err) => {
+                println!("REFTEST ERROR {}: {}", example.name, err);
+                failures += 1;
+                continue;
+            }
+        };
+        let reference_path = options.reference_dir.join(format!("{}.png", example.name));
+
+        if options.bless {
+            let written = fs::create_dir_all(&options.reference_dir)
+                .and_then(|_| capture.write_png(&reference_path));
+            match written {
+                Ok(()) => println!("REFTEST BLESS {} -> {:?}", example.name, reference_path),
+                Err(err) => {
+                    println!("REFTEST ERROR {}: failed to write {:?}: {}", example.name, reference_path, err);
+                    failures += 1;
+                }
+            }
+            continue;
+        }
+
+        let reference = match read_png(&reference_path) {
+            Ok(reference) => reference,
+            Err(err) => {
+                println!("REFTEST FAIL {}: failed to read {:?}: {}", example.name, reference_path, err);
+                failures += 1;
+                continue;
+            }
+        };
+
+        let comparison = match compare(&capture, &reference, options.tolerance) {
+            Some(comparison) => comparison,
+            None => {
+                println!(
+                    "REFTEST FAIL {}: rendered {}x{}, reference is {}x{}",
+                    example.name,
+                    capture.size.width, capture.size.height,
+                    reference.size.width, reference.size.height,
+                );
+                failures += 1;
+                continue;
+            }
+        };
+
+        if comparison.passes(options.max_differing_pixels) {
+            println!("REFTEST PASS {}", example.name);
+            continue;
+        }
+
+        failures += 1;
+        let diff_path = options.output_dir.join(format!("{}-diff.png", example.name));
+        let written = fs::create_dir_all(&options.output_dir)
+            .and_then(|_| comparison.diff.write_png(&diff_path));
+        println!(
+            "REFTEST FAIL {}: {} pixels differ, max channel difference {}",
+            example.name, comparison.differing_pixels, comparison.max_difference,
+        );
+        match written {
+            Ok(()) => println!("    diff written to {:?}", diff_path),
+            Err(err) => println!("    failed to write {:?}: {}", diff_path, err),
+        }
+    }
+
+    println!("REFTEST {} of {} failed", failures, selected.len());
+    failures == 0
+}
+
+/// Compares two images of the same size. The diff shows the reference
+/// faded out, with the differing pixels in red.
+fn compare(capture: &Capture, reference: &Capture, tolerance: u8) -> Option<Comparison> {
+    if capture.size != reference.size {
+        return None;
+    }
+
+    let mut differing_pixels = 0;
+    let mut max_difference = 0;
+    let mut diff = Vec::with_capacity(reference.pixels.len());
+
+    for (actual, expected) in capture.pixels.chunks(4).zip(reference.pixels.chunks(4)) {
+        let difference = actual
+            .iter()
+            .zip(expected)
+            .map(|(a, b)| a.max(b) - a.min(b))
+            .max()
+            .unwrap_or(0);
+        max_difference = max_difference.max(difference);
+
+        if difference > tolerance {
+            differing_pixels += 1;
+            diff.extend_from_slice(&[255, 0, 0, 255]);
+        } else {
+            let luma = (expected[0] as u32 * 3 + expected[1] as u32 * 6 + expected[2] as u32) / 10;
+            let faded = (128 + luma / 2) as u8;
+            diff.extend_from_slice(&[faded, faded, faded, 255]);
+        }
+    }
+
+    Some(Comparison {
+        differing_pixels,
+        max_difference,
+        diff: Capture {
+            size: reference.size,
+            device_pixel_ratio: reference.device_pixel_ratio,
+            pixels: diff,
+        },
+    })
+}
+
+/// Reads an 8 bit RGB or RGBA PNG as RGBA8.
+fn read_png(path: &Path) -> io::Result<Capture> {
+    let invalid = |err| io::Error::new(io::ErrorKind::InvalidData, err);
+
+    let decoder = png::Decoder::new(File::open(path)?);
+    let (info, mut reader) = decoder.read_info().map_err(invalid)?;
+    let mut buffer = vec![0; info.buffer_size()];
+    reader.next_frame(&mut buffer).map_err(invalid)?;
+
+    let pixels = match (info.color_type, info.bit_depth) {
+        (png::ColorType::RGBA, png::BitDepth::Eight) => buffer,
+        (png::ColorType::RGB, png::BitDepth::Eight) => buffer
+            .chunks(3)
+            .flat_map(|rgb| vec![rgb[0], rgb[1], rgb[2], 255])
+            .collect(),
+        (color_type, bit_depth) => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("unsupported PNG format {:?} {:?}", color_type, bit_depth),
+            ));
+        }
+    };
+
+    Ok(Capture {
+        size: DeviceIntSize::new(info.width as i32, info.height as i32),
+        device_pixel_ratio: REFTEST_DEVICE_PIXEL_RATIO,
+        pixels,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, process};
+
+    fn capture(width: i32, height: i32, pixels: Vec<u8>) -> Capture {
+        Capture {
+            size: DeviceIntSize::new(width, height),
+            device_pixel_ratio: REFTEST_DEVICE_PIXEL_RATIO,
+            pixels,
+        }
+    }
+
+    /// Writes a PNG into the temporary directory, named after the test.
+    fn write_png(name: &str, color_type: png::ColorType, bit_depth: png::BitDepth, data: &[u8]) -> PathBuf {
+        let path = env::temp_dir().join(format!("demo-reftest-{}-{}.png", process::id(), name));
+        let mut encoder = png::Encoder::new(File::create(&path).unwrap(), 2, 1);
+        encoder.set_color(color_type);
+        encoder.set_depth(bit_depth);
+        encoder.write_header().unwrap().write_image_data(data).unwrap();
+        path
+    }
+
+    #[test]
+    fn identical_images_pass() {
+        let pixels = vec![10, 20, 30, 255, 40, 50, 60, 255];
+        let comparison = compare(&capture(2, 1, pixels.clone()), &capture(2, 1, pixels), 0).unwrap();
+        assert_eq!(comparison.differing_pixels, 0);
+        assert_eq!(comparison.max_difference, 0);
+        assert!(comparison.passes(0));
+    }
+
+    #[test]
+    fn differences_within_the_tolerance_pass() {
+        let reference = capture(2, 1, vec![10, 20, 30, 255, 40, 50, 60, 255]);
+        let rendered = capture(2, 1, vec![13, 20, 30, 255, 40, 48, 60, 255]);
+
+        let comparison = compare(&rendered, &reference, 3).unwrap();
+        assert_eq!(comparison.differing_pixels, 0);
+        assert_eq!(comparison.max_difference, 3);
+
+        let comparison = compare(&rendered, &reference, 2).unwrap();
+        assert_eq!(comparison.differing_pixels, 1);
+        assert_eq!(comparison.max_difference, 3);
+        // The differing pixel is red in the diff, the other one faded.
+        assert_eq!(&comparison.diff.pixels[..4], &[255, 0, 0, 255]);
+        assert_eq!(&comparison.diff.pixels[4..], &[152, 152, 152, 255]);
+    }
+
+    #[test]
+    fn differing_pixels_are_counted_against_the_maximum() {
+        let reference = capture(2, 1, vec![0; 8]);
+        let rendered = capture(2, 1, vec![255; 8]);
+        let comparison = compare(&rendered, &reference, 0).unwrap();
+        assert_eq!(comparison.differing_pixels, 2);
+        assert!(!comparison.passes(1));
+        assert!(comparison.passes(2));
+    }
+
+    #[test]
+    fn images_of_different_sizes_fail() {
+        assert!(compare(&capture(2, 1, vec![0; 8]), &capture(1, 2, vec![0; 8]), 255).is_none());
+    }
+
+    #[test]
+    fn reads_rgb_and_rgba_pngs() {
+        let path = write_png("rgba", png::ColorType::RGBA, png::BitDepth::Eight, &[1, 2, 3, 4, 5, 6, 7, 8]);
+        let rgba = read_png(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(rgba.size, DeviceIntSize::new(2, 1));
+        assert_eq!(rgba.pixels, [1, 2, 3, 4, 5, 6, 7, 8]);
+
+        let path = write_png("rgb", png::ColorType::RGB, png::BitDepth::Eight, &[1, 2, 3, 5, 6, 7]);
+        let rgb = read_png(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(rgb.pixels, [1, 2, 3, 255, 5, 6, 7, 255]);
+    }
+
+    #[test]
+    fn rejects_other_pngs() {
+        let path = write_png("grayscale", png::ColorType::Grayscale, png::BitDepth::Eight, &[1, 2]);
+        let err = read_png(&path).err().unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(err.to_string(), "unsupported PNG format Grayscale Eight");
+
+        let err = read_png(&env::temp_dir().join("demo-reftest-missing.png")).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+    }
+}
@@ -0,0 +1,290 @@
+// Windows has no render thread, see `SyncWebrenderSurfman`, which leaves
+// what only `RenderThread::spawn` uses unused there.
+#![cfg_attr(target_os = "windows", allow(dead_code, unused_imports))]
+
+use crate::{
+    compositor::{self, Capture, LocalRenderer, NewFrame},
+    error::Error,
+    webrender_surfman::WebrenderSurfman,
+    window::EmbedderCoordinates
+};
+use std::{
+    collections::HashMap, iter,
+    sync::mpsc::{self, Receiver, Sender},
+    thread::{self, JoinHandle}
+};
+use webrender::{CompositorConfig, RendererOptions};
+use webrender::api::{DocumentId, Epoch, PipelineId, RenderApiSender, RenderNotifier};
+
+/// What the compositor asks of its render thread.
+enum RenderMsg {
+    /// Webrender built a frame, sent by the `RenderThreadNotifier`.
+    FrameReady { composite_needed: bool },
+    /// Composite the next frame even if webrender has nothing new.
+    Invalidate,
+    /// The window changed size or device pixel ratio.
+    Resize(EmbedderCoordinates),
+    /// Composite a frame and send its pixels before presenting it.
+    ReadPixels(Sender<Capture>),
+    Exit,
+}
+
+/// What the render thread tells the compositor, waking the event loop.
+enum RenderReport {
+    /// A frame was presented, with the epochs of the pipelines that changed
+    /// since the last frame.
+    Presented(Vec<(PipelineId, Epoch)>),
+    /// Rendering failed, the thread waits for `RenderMsg::Exit`.
+    Failed(Error),
+}
+
+/// Wakes the render thread when webrender built a frame, instead of the
+/// event loop. Other wake ups still go to the event loop.
+struct RenderThreadNotifier {
+    sender: Sender<RenderMsg>,
+    notifier: Box<dyn RenderNotifier>,
+}
+
+impl RenderNotifier for RenderThreadNotifier {
+    fn clone(&self) -> Box<dyn RenderNotifier> {
+        Box::new(RenderThreadNotifier {
+            sender: self.sender.clone(),
+            notifier: self.notifier.clone(),
+        })
+    }
+
+    fn wake_up(&self) {
+        self.notifier.wake_up();
+    }
+
+    fn new_frame_ready(&self,
+                       _: DocumentId,
+                       _scrolled: bool,
+                       composite_needed: bool,
+                       _render_time: Option<u64>) {
+        let _ = self.sender.send(RenderMsg::FrameReady { composite_needed });
+    }
+}
+
+/// `RendererOptions` on their way to the render thread.
+struct ThreadOptions(RendererOptions);
+
+// SAFETY: besides plain data, `RendererOptions` holds the boxed
+// `blob_image_handler`, `recorder`, `thread_listener`, `scene_builder_hooks`
+// and `sampler`, all `Send` by the bounds of their traits, the `workers`
+// thread pool in an `Arc`, and the `size_of_op` and `enclosing_size_of_op`
+// function pointers. The fields that are not `Send`, the `Rc` of
+// `cached_programs` and a native compositor in `compositor_config`, are
+// rejected by `RenderThread::spawn`.
+unsafe impl Send for ThreadOptions {}
+
+/// A thread owning the GL context and the webrender renderer of a window. It
+/// composites and presents whenever webrender built a frame, so a slow frame
+/// doesn't hold up the event loop.
+pub struct RenderThread {
+    sender: Sender<RenderMsg>,
+    reports: Receiver<RenderReport>,
+    thread: JoinHandle<()>,
+    /// The latest epoch presented for each pipeline.
+    presented_epochs: HashMap<PipelineId, Epoch>,
+    /// Frames presented since the last `take_presented`.
+    presented_frames: u64,
+    /// The error that stopped the thread, until `take_presented`.
+    error: Option<Error>,
+}
+
+impl RenderThread {
+    /// Moves `webrender_surfman` to a new render thread and creates the
+    /// renderer there. `notifier` wakes the event loop after each frame
+    /// presented. Also returns the frames webrender reports, like
+    /// `compositor::reporting_notifier`. Fails for a native compositor or a
+    /// program cache, and on Windows, see `SyncWebrenderSurfman` for the
+    /// platforms the move is sound on.
+    #[cfg(not(target_os = "windows"))]
+    pub fn spawn(
+        webrender_surfman: WebrenderSurfman,
+        notifier: Box<dyn RenderNotifier>,
+        options: RendererOptions,
+        coordinates: EmbedderCoordinates,
+    ) -> Result<(RenderThread, RenderApiSender, Receiver<NewFrame>), Error> {
+        // Neither can move to the thread, see `ThreadOptions`.
+        if let CompositorConfig::Native { .. } = options.compositor_config {
+            return Err(Error::RenderThreadUnsupported("a native compositor"));
+        }
+        if options.cached_programs.is_some() {
+            return Err(Error::RenderThreadUnsupported("a program cache"));
+        }
+
+        let (sender, receiver) = mpsc::channel();
+        let (report_sender, reports) = mpsc::channel();
+        let (startup_sender, startup) = mpsc::channel();
+
+        let thread_notifier = Box::new(RenderThreadNotifier {
+            sender: sender.clone(),
+            notifier: notifier.clone(),
+        });
+        let (thread_notifier, new_frames) = compositor::reporting_notifier(thread_notifier);
+        let webrender_surfman = webrender_surfman.into_sync().map_err(|_| Error::RenderThread)?;
+        let options = ThreadOptions(options);
+
+        let thread = thread::Builder::new()
+            .name("Render".to_owned())
+            .spawn(move || {
+                let renderer = LocalRenderer::new(
+                    webrender_surfman.into_local(),
+                    thread_notifier,
+                    options.0,
+                    coordinates.framebuffer,
+                );
+                let mut renderer = match renderer {
+                    Ok((renderer, api_sender)) => {
+                        let _ = startup_sender.send(Ok(api_sender));
+                        renderer
+                    }
+                    Err(err) => {
+                        let _ = startup_sender.send(Err(err));
+                        return;
+                    }
+                };
+
+                if let Err(err) = render_frames(&mut renderer, &receiver, &report_sender, &*notifier, coordinates) {
+                    let _ = report_sender.send(RenderReport::Failed(err));
+                    notifier.wake_up();
+                    // Keep the renderer until the compositor lets it go.
+                    while let Ok(msg) = receiver.recv() {
+                        if let RenderMsg::Exit = msg {
+                            break;
+                        }
+                    }
+                }
+                renderer.deinit();
+            })
+            .map_err(|_| Error::RenderThread)?;
+
+        let api_sender = startup.recv().map_err(|_| Error::RenderThread)??;
+        let render_thread = RenderThread {
+            sender,
+            reports,
+            thread,
+            presented_epochs: HashMap::new(),
+            presented_frames: 0,
+            error: None,
+        };
+        Ok((render_thread, api_sender, new_frames))
+    }
+
+    /// Render threads are not supported on Windows, see
+    /// `SyncWebrenderSurfman`.
+    #[cfg(target_os = "windows")]
+    pub fn spawn(
+        _webrender_surfman: WebrenderSurfman,
+        _notifier: Box<dyn RenderNotifier>,
+        _options: RendererOptions,
+        _coordinates: EmbedderCoordinates,
+    ) -> Result<(RenderThread, RenderApiSender, Receiver<NewFrame>), Error> {
+        Err(Error::RenderThreadUnsupported("the surfman backends of Windows"))
+    }
+
+    /// Receives the reports of the thread, returns whether it presented
+    /// frames or failed since the last `take_presented`.
+    pub fn has_presented(&mut self) -> bool {
+        for report in self.reports.try_iter() {
+            match report {
+                RenderReport::Presented(epochs) => {
+                    self.presented_epochs.extend(epochs);
+                    self.presented_frames += 1;
+                }
+                RenderReport::Failed(err) => self.error = Some(err),
+            }
+        }
+        self.presented_frames > 0 || self.error.is_some()
+    }
+
+    /// The number of frames presented since the last call, or the error
+    /// that stopped the thread.
+    pub fn take_presented(&mut self) -> Result<u64, Error> {
+        self.has_presented();
+        if let Some(err) = self.error.take() {
+            return Err(err);
+        }
+        Ok(std::mem::replace(&mut self.presented_frames, 0))
+    }
+
+    /// The epoch of `pipeline_id` in the last frame presented.
+    pub fn current_epoch(&self, pipeline_id: PipelineId) -> Option<Epoch> {
+        self.presented_epochs.get(&pipeline_id).cloned()
+    }
+
+    pub fn invalidate(&self) {
+        let _ = self.sender.send(RenderMsg::Invalidate);
+    }
+
+    pub fn resize(&self, coordinates: EmbedderCoordinates) {
+        let _ = self.sender.send(RenderMsg::Resize(coordinates));
+    }
+
+    /// Blocks until the thread composited a frame and read it back.
+    pub fn read_pixels(&self) -> Result<Capture, Error> {
+        let (sender, receiver) = mpsc::channel();
+        self.sender
+            .send(RenderMsg::ReadPixels(sender))
+            .map_err(|_| Error::RenderThread)?;
+        receiver.recv().map_err(|_| Error::RenderThread)
+    }
+
+    /// Deinitializes the renderer on the thread and waits for it to end.
+    pub fn exit(self) {
+        let _ = self.sender.send(RenderMsg::Exit);
+        if self.thread.join().is_err() {
+            println!("The render thread panicked");
+        }
+    }
+}
+
+/// Composites and presents a frame whenever the messages queued ask for
+/// one, until `RenderMsg::Exit`.
+fn render_frames(
+    renderer: &mut LocalRenderer,
+    receiver: &Receiver<RenderMsg>,
+    reports: &Sender<RenderReport>,
+    notifier: &dyn RenderNotifier,
+    mut coordinates: EmbedderCoordinates,
+) -> Result<(), Error> {
+    let mut composite_needed = true;
+    let mut read_pixels = Vec::new();
+
+    // Block until there is something to do, then take everything queued so
+    // that a frame covers all of it.
+    while let Ok(msg) = receiver.recv() {
+        for msg in iter::once(msg).chain(receiver.try_iter()) {
+            match msg {
+                RenderMsg::FrameReady { composite_needed: needed } => composite_needed |= needed,
+                RenderMsg::Invalidate => composite_needed = true,
+                RenderMsg::Resize(new_coordinates) => {
+                    coordinates = new_coordinates;
+                    renderer.resize(coordinates.framebuffer)?;
+                    composite_needed = true;
+                }
+                RenderMsg::ReadPixels(sender) => {
+                    read_pixels.push(sender);
+                    composite_needed = true;
+                }
+                RenderMsg::Exit => return Ok(()),
+            }
+        }
+        if !composite_needed {
+            continue;
+        }
+
+        renderer.composite(&coordinates)?;
+        for sender in read_pixels.drain(..) {
+            let _ = sender.send(renderer.read_pixels(&coordinates));
+        }
+        renderer.present()?;
+        composite_needed = false;
+
+        let _ = reports.send(RenderReport::Presented(renderer.flush_rendered_epochs()));
+        notifier.wake_up();
+    }
+    Ok(())
+}
@@ -0,0 +1,559 @@
+use crate::{
+    app::{App, RunOptions},
+    compositor::Compositor,
+    fonts::{FontDescriptor, FontSource},
+    images::Image,
+    text::TextLayout
+};
+use euclid::{Angle, SideOffsets2D};
+use serde::Deserialize;
+use std::{collections::HashMap, fmt, fs, io, mem, path::{Path, PathBuf}};
+use webrender::api::{units::*, *};
+
+// Geometry is written as plain arrays in scene files.
+type Rect = [f32; 4]; // x, y, width, height
+type Point = [f32; 2];
+type Color = [f32; 4]; // r, g, b, a in 0..1
+
+/// The root of a scene file.
+///
+/// ```yaml
+/// title: Hello
+/// size: [800, 600]
+/// font: { path: ../fonts/FreeSans.ttf, size: 32 }
+/// # or a system font: { family: sans-serif, weight: 700, size: 32 }
+/// items:
+///   - type: rect
+///     bounds: [10, 10, 100, 100]
+///     color: [0, 1, 0, 1]
+/// ```
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Scene {
+    pub title: Option<String>,
+    pub size: Option<(u32, u32)>,
+    pub clear_color: Option<Color>,
+    pub font: Option<SceneFont>,
+    #[serde(default)]
+    pub items: Vec<Item>,
+}
+
+/// A font file, or a system font when `family` is given instead of `path`.
+#[derive(Debug, Deserialize)]
+pub struct SceneFont {
+    /// Relative to the scene file.
+    pub path: Option<PathBuf>,
+    pub family: Option<String>,
+    pub weight: Option<u16>,
+    #[serde(default)]
+    pub italic: bool,
+    pub size: f32,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Glyph {
+    pub index: u32,
+    pub point: Point,
+}
+
+/// A display item. Items nested in `items` are positioned in the space of
+/// their parent and clipped by it.
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+pub enum Item {
+    Rect {
+        bounds: Rect,
+        color: Color,
+    },
+    /// Either `text`, laid out within the width of the bounds, or
+    /// positioned `glyphs`.
+    Text {
+        bounds: Rect,
+        #[serde(default = "black")]
+        color: Color,
+        text: Option<String>,
+        #[serde(default)]
+        glyphs: Vec<Glyph>,
+    },
+    /// A PNG, relative to the scene file.
+    Image {
+        bounds: Rect,
+        src: PathBuf,
+    },
+    StackingContext {
+        #[serde(default)]
+        origin: Point,
+        opacity: Option<f32>,
+        #[serde(default)]
+        items: Vec<Item>,
+    },
+    ReferenceFrame {
+        #[serde(default)]
+        origin: Point,
+        /// Rotation around the origin, in degrees.
+        #[serde(default)]
+        rotate: f32,
+        #[serde(default = "one")]
+        scale: f32,
+        #[serde(default)]
+        items: Vec<Item>,
+    },
+    Clip {
+        rect: Rect,
+        #[serde(default)]
+        radius: f32,
+        #[serde(default)]
+        items: Vec<Item>,
+    },
+    ScrollFrame {
+        content: Rect,
+        clip: Rect,
+        #[serde(default)]
+        items: Vec<Item>,
+    },
+    StickyFrame {
+        bounds: Rect,
+        /// Top, right, bottom and left margins, `~` to not stick on a side.
+        #[serde(default)]
+        margins: [Option<f32>; 4],
+        #[serde(default)]
+        vertical: [f32; 2],
+        #[serde(default)]
+        horizontal: [f32; 2],
+        #[serde(default)]
+        items: Vec<Item>,
+    },
+}
+
+fn black() -> Color {
+    [0.0, 0.0, 0.0, 1.0]
+}
+
+fn one() -> f32 {
+    1.0
+}
+
+#[derive(Debug)]
+pub enum SceneError {
+    Io(PathBuf, io::Error),
+    Parse(PathBuf, serde_yaml::Error),
+}
+
+impl fmt::Display for SceneError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SceneError::Io(path, err) => write!(f, "Failed to read {:?}: {}", path, err),
+            SceneError::Parse(path, err) => write!(f, "Failed to parse {:?}: {}", path, err),
+        }
+    }
+}
+
+impl std::error::Error for SceneError {}
+
+impl Scene {
+    pub fn load(path: &Path) -> Result<Scene, SceneError> {
+        let source = fs::read_to_string(path).map_err(|err| SceneError::Io(path.to_owned(), err))?;
+        serde_yaml::from_str(&source).map_err(|err| SceneError::Parse(path.to_owned(), err))
+    }
+}
+
+/// An `App` drawing a scene file.
+pub struct SceneApp {
+    path: PathBuf,
+    scene: Scene,
+    images: HashMap<PathBuf, ImageKey>,
+    /// Set by `reload`, the uploaded images are replaced on the next build.
+    images_stale: bool,
+}
+
+impl SceneApp {
+    pub fn load(path: &Path) -> Result<SceneApp, SceneError> {
+        Ok(SceneApp {
+            path: path.to_owned(),
+            scene: Scene::load(path)?,
+            images: HashMap::new(),
+            images_stale: false,
+        })
+    }
+
+    /// Applies the scene's title and size, unless the options override them.
+    pub fn apply_to(&self, options: &mut RunOptions) {
+        if options.title.is_none() {
+            options.title = Some(self.scene.title.clone().unwrap_or_else(|| {
+                self.path.display().to_string()
+            }));
+        }
+        if options.size.is_none() {
+            options.size = self.scene.size;
+        }
+    }
+
+    fn resolve(&self, path: &Path) -> PathBuf {
+        self.path.parent().unwrap_or_else(|| Path::new("")).join(path)
+    }
+
+    /// Uploads the images referenced by the scene that are not uploaded yet.
+    /// After a reload, the images still referenced are updated under their
+    /// key and the others deleted.
+    fn upload_images(&mut self, compositor: &mut Compositor) {
+        let mut sources = Vec::new();
+        collect_images(&self.scene.items, &mut sources);
+
+        let mut stale_images = HashMap::new();
+        if self.images_stale {
+            stale_images = mem::replace(&mut self.images, HashMap::new());
+            self.images_stale = false;
+        }
+        for src in sources {
+            let path = self.resolve(&src);
+            if self.images.contains_key(&path) {
+                continue;
+            }
+            match Image::open(&path) {
+                Ok(image) => {
+                    let key = match stale_images.remove(&path) {
+                        Some(key) => {
+                            compositor.update_image(key, image);
+                            key
+                        }
+                        None => compositor.add_image(image),
+                    };
+                    self.images.insert(path, key);
+                }
+                Err(err) => println!("Skipping image {:?}: {}", path, err),
+            }
+        }
+        // Unused now, or failed to load again.
+        for (_, key) in stale_images {
+            compositor.delete_image(key);
+        }
+    }
+
+    fn build_items(
+        &self,
+        builder: &mut DisplayListBuilder,
+        compositor: &Compositor,
+        items: &[Item],
+        space_and_clip: SpaceAndClipInfo,
+        font_instance_key: Option<FontInstanceKey>,
+    ) {
+        for item in items {
+            match item {
+                Item::Rect { bounds, color } => {
+                    let bounds = rect(bounds);
+                    builder.push_rect(
+                        &CommonItemProperties::new(bounds, space_and_clip),
+                        bounds,
+                        color_f(color),
+                    );
+                }
+                Item::Text { bounds, color, text, glyphs } => {
+                    let font_instance_key = match font_instance_key {
+                        Some(key) => key,
+                        None => {
+                            println!("Skipping text item, the scene has no font");
+                            continue;
+                        }
+                    };
+                    let bounds = rect(bounds);
+                    let glyphs: Vec<GlyphInstance> = match (text, compositor.font_instance(font_instance_key)) {
+                        (Some(text), Some((font, size))) => {
+                            TextLayout::new(&font, text, size, bounds.size.width).glyphs_at(bounds.origin)
+                        }
+                        (Some(_), None) => {
+                            println!("Skipping text item, the scene's font could not be parsed");
+                            continue;
+                        }
+                        (None, _) => glyphs
+                            .iter()
+                            .map(|glyph| GlyphInstance {
+                                index: glyph.index,
+                                point: point(&glyph.point),
+                            })
+                            .collect(),
+                    };
+                    builder.push_text(
+                        &CommonItemProperties::new(bounds, space_and_clip),
+                        bounds,
+                        &glyphs,
+                        font_instance_key,
+                        color_f(color),
+                        None,
+                    );
+                }
+                Item::Image { bounds, src } => {
+                    let key = match self.images.get(&self.resolve(src)) {
+                        Some(key) => *key,
+                        None => continue,
+                    };
+                    let bounds = rect(bounds);
+                    builder.push_image(
+                        &CommonItemProperties::new(bounds, space_and_clip),
+                        bounds,
+                        ImageRendering::Auto,
+                        AlphaType::PremultipliedAlpha,
+                        key,
+                        ColorF::WHITE,
+                    );
+                }
+                Item::StackingContext { origin, opacity, items } => {
+                    let filters: Vec<FilterOp> = opacity
+                        .iter()
+                        .map(|opacity| FilterOp::Opacity(PropertyBinding::Value(*opacity), *opacity))
+                        .collect();
+                    builder.push_simple_stacking_context_with_filters(
+                        point(origin),
+                        space_and_clip.spatial_id,
+                        PrimitiveFlags::IS_BACKFACE_VISIBLE,
+                        &filters,
+                        &[],
+                        &[],
+                    );
+                    self.build_items(builder, compositor, items, space_and_clip, font_instance_key);
+                    builder.pop_stacking_context();
+                }
+                Item::ReferenceFrame { origin, rotate, scale, items } => {
+                    let transform = LayoutTransform::create_scale(*scale, *scale, 1.0)
+                        .post_transform(&LayoutTransform::create_rotation(
+                            0.0, 0.0, 1.0, Angle::degrees(*rotate),
+                        ));
+                    let spatial_id = builder.push_reference_frame(
+                        point(origin),
+                        space_and_clip.spatial_id,
+                        TransformStyle::Flat,
+                        PropertyBinding::Value(transform),
+                        ReferenceFrameKind::Transform,
+                    );
+                    builder.push_simple_stacking_context(
+                        LayoutPoint::zero(),
+                        spatial_id,
+                        PrimitiveFlags::IS_BACKFACE_VISIBLE,
+                    );
+                    let inner = SpaceAndClipInfo {
+                        spatial_id,
+                        clip_id: space_and_clip.clip_id,
+                    };
+                    self.build_items(builder, compositor, items, inner, font_instance_key);
+                    builder.pop_stacking_context();
+                    builder.pop_reference_frame();
+                }
+                Item::Clip { rect: clip_rect, radius, items } => {
+                    let clip_rect = rect(clip_rect);
+                    let clip_id = if *radius > 0.0 {
+                        builder.define_clip_rounded_rect(
+                            &space_and_clip,
+                            ComplexClipRegion {
+                                rect: clip_rect,
+                                radii: BorderRadius::uniform(*radius),
+                                mode: ClipMode::Clip,
+                            },
+                        )
+                    } else {
+                        builder.define_clip_rect(&space_and_clip, clip_rect)
+                    };
+                    let inner = SpaceAndClipInfo {
+                        spatial_id: space_and_clip.spatial_id,
+                        clip_id,
+                    };
+                    self.build_items(builder, compositor, items, inner, font_instance_key);
+                }
+                Item::ScrollFrame { content, clip, items } => {
+                    let inner = builder.define_scroll_frame(
+                        &space_and_clip,
+                        None,
+                        rect(content),
+                        rect(clip),
+                        ScrollSensitivity::ScriptAndInputEvents,
+                        LayoutVector2D::zero(),
+                    );
+                    self.build_items(builder, compositor, items, inner, font_instance_key);
+                }
+                Item::StickyFrame { bounds, margins, vertical, horizontal, items } => {
+                    let spatial_id = builder.define_sticky_frame(
+                        space_and_clip.spatial_id,
+                        rect(bounds),
+                        SideOffsets2D::new(margins[0], margins[1], margins[2], margins[3]),
+                        StickyOffsetBounds::new(vertical[0], vertical[1]),
+                        StickyOffsetBounds::new(horizontal[0], horizontal[1]),
+                        LayoutVector2D::zero(),
+                    );
+                    let inner = SpaceAndClipInfo {
+                        spatial_id,
+                        clip_id: space_and_clip.clip_id,
+                    };
+                    self.build_items(builder, compositor, items, inner, font_instance_key);
+                }
+            }
+        }
+    }
+}
+
+impl App for SceneApp {
+    fn clear_color(&self) -> Option<ColorF> {
+        self.scene.clear_color.as_ref().map(color_f).or(Some(ColorF::WHITE))
+    }
+
+    fn add_font(&self) -> Option<(FontSource, f32)> {
+        let font = self.scene.font.as_ref()?;
+        let source = match (&font.path, &font.family) {
+            (Some(path), _) => FontSource::Path(self.resolve(path)),
+            (None, Some(family)) => {
+                let mut descriptor = FontDescriptor::new(family).weight(font.weight.unwrap_or(400));
+                descriptor.italic = font.italic;
+                FontSource::System(descriptor)
+            }
+            (None, None) => {
+                println!("The scene's font needs a path or a family");
+                return None;
+            }
+        };
+        Some((source, font.size))
+    }
+
+    fn watched_files(&self) -> Vec<PathBuf> {
+        let mut files = vec![self.path.clone()];
+        if let Some((FontSource::Path(path), _)) = self.add_font() {
+            files.push(path);
+        }
+        let mut images = Vec::new();
+        collect_images(&self.scene.items, &mut images);
+        files.extend(images.iter().map(|src| self.resolve(src)));
+        files
+    }
+
+    fn reload(&mut self) -> bool {
+        match Scene::load(&self.path) {
+            Ok(scene) => {
+                self.scene = scene;
+                self.images_stale = true;
+                true
+            }
+            Err(err) => {
+                // Keep showing the last good scene until the file is fixed.
+                println!("{}", err);
+                false
+            }
+        }
+    }
+
+    fn build_display_list(
+        &mut self,
+        compositor: &mut Compositor,
+        pipeline_id: PipelineId,
+        _document_id: DocumentId,
+        font_instance_key: Option<FontInstanceKey>
+    ) -> DisplayListBuilder {
+        self.upload_images(compositor);
+
+        let mut builder = DisplayListBuilder::new(pipeline_id, compositor.get_layout_size());
+        let space_and_clip = SpaceAndClipInfo::root_scroll(pipeline_id);
+
+        builder.push_simple_stacking_context(
+            LayoutPoint::zero(),
+            space_and_clip.spatial_id,
+            PrimitiveFlags::IS_BACKFACE_VISIBLE,
+        );
+        self.build_items(&mut builder, compositor, &self.scene.items, space_and_clip, font_instance_key);
+        builder.pop_stacking_context();
+
+        builder
+    }
+}
+
+fn collect_images(items: &[Item], sources: &mut Vec<PathBuf>) {
+    for item in items {
+        match item {
+            Item::Image { src, .. } => sources.push(src.clone()),
+            Item::StackingContext { items, .. }
+            | Item::ReferenceFrame { items, .. }
+            | Item::Clip { items, .. }
+            | Item::ScrollFrame { items, .. }
+            | Item::StickyFrame { items, .. } => collect_images(items, sources),
+            Item::Rect { .. } | Item::Text { .. } => {}
+        }
+    }
+}
+
+fn rect(rect: &Rect) -> LayoutRect {
+    LayoutRect::new(LayoutPoint::new(rect[0], rect[1]), LayoutSize::new(rect[2], rect[3]))
+}
+
+fn point(point: &Point) -> LayoutPoint {
+    LayoutPoint::new(point[0], point[1])
+}
+
+fn color_f(color: &Color) -> ColorF {
+    ColorF::new(color[0], color[1], color[2], color[3])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, process};
+
+    #[test]
+    fn loads_the_basic_scene() {
+        let scene = Scene::load(Path::new("res/scenes/basic.yaml")).unwrap();
+        assert_eq!(scene.title.as_deref(), Some("Basic Scene"));
+        assert_eq!(scene.size, Some((800, 600)));
+        assert_eq!(scene.clear_color, Some([1.0, 1.0, 1.0, 1.0]));
+        let font = scene.font.unwrap();
+        assert_eq!(font.path, Some(PathBuf::from("../fonts/FreeSans.ttf")));
+        assert_eq!(font.size, 32.0);
+        assert!(!font.italic);
+
+        assert_eq!(scene.items.len(), 5);
+        match scene.items[0] {
+            Item::Rect { bounds, color } => {
+                assert_eq!(bounds, [100.0, 200.0, 100.0, 200.0]);
+                assert_eq!(color, [0.0, 1.0, 0.0, 1.0]);
+            }
+            ref item => panic!("expected a rect, got {:?}", item),
+        }
+        match scene.items[2] {
+            Item::Text { ref text, color, ref glyphs, .. } => {
+                assert!(text.is_some());
+                assert_eq!(color, black());
+                assert!(glyphs.is_empty());
+            }
+            ref item => panic!("expected text, got {:?}", item),
+        }
+        match scene.items[3] {
+            Item::ReferenceFrame { origin, rotate, scale, ref items } => {
+                assert_eq!(origin, [500.0, 250.0]);
+                assert_eq!(rotate, 15.0);
+                assert_eq!(scale, 1.0);
+                assert!(matches!(items[0], Item::Clip { radius, .. } if radius == 20.0));
+            }
+            ref item => panic!("expected a reference frame, got {:?}", item),
+        }
+        match scene.items[4] {
+            Item::StackingContext { ref items, .. } => match items[0] {
+                Item::ScrollFrame { ref items, .. } => match items[1] {
+                    Item::StickyFrame { margins, vertical, horizontal, .. } => {
+                        assert_eq!(margins, [Some(10.0), None, Some(10.0), None]);
+                        assert_eq!(vertical, [-40.0, 60.0]);
+                        assert_eq!(horizontal, [0.0, 0.0]);
+                    }
+                    ref item => panic!("expected a sticky frame, got {:?}", item),
+                },
+                ref item => panic!("expected a scroll frame, got {:?}", item),
+            },
+            ref item => panic!("expected a stacking context, got {:?}", item),
+        }
+    }
+
+    #[test]
+    fn reports_malformed_scenes() {
+        let path = env::temp_dir().join(format!("demo-scene-{}.yaml", process::id()));
+        fs::write(&path, "items:\n  - type: circle\n    bounds: [0, 0, 10, 10]\n").unwrap();
+        match Scene::load(&path) {
+            Err(SceneError::Parse(error_path, _)) => assert_eq!(error_path, path),
+            other => panic!("expected a parse error, got {:?}", other.map(|_| ())),
+        }
+        fs::remove_file(&path).unwrap();
+
+        let missing = Path::new("res/scenes/missing.yaml");
+        assert!(matches!(Scene::load(missing), Err(SceneError::Io(..))));
+    }
+}
@@ -0,0 +1,68 @@
+use crate::{
+    error::Error,
+    webrender_surfman::{WebrenderSurfman, WebrenderSurfmanBuilder}
+};
+use euclid::default::Size2D;
+use gleam::gl;
+use std::rc::Rc;
+use surfman::{Device, SurfaceType};
+use surfman_chains::SwapChain;
+
+/// A GL context of its own rendering offscreen, e.g. a video decoder or a
+/// game view. The frames it presents are shown through
+/// `ExternalImages::set_producer`.
+pub struct SurfaceProducer {
+    webrender_surfman: WebrenderSurfman,
+    gl: Rc<dyn gl::Gl>,
+    size: Size2D<i32>,
+}
+
+impl SurfaceProducer {
+    /// Creates a context like the one of `consumer` on the same adapter, so
+    /// that the consumer can import its surfaces.
+    pub fn new(consumer: &WebrenderSurfman, size: Size2D<i32>) -> Result<Self, Error> {
+        let attributes = consumer.context_attributes();
+        let webrender_surfman = WebrenderSurfmanBuilder::default()
+            .flags(attributes.flags)
+            .gl_versions(&[attributes.version])
+            .gles_versions(&[attributes.version])
+            .build_with_adapter(&consumer.connection(), &consumer.adapter(), SurfaceType::Generic { size })
+            .map_err(Error::Context)?;
+        let gl = webrender_surfman.gl_bindings();
+        Ok(SurfaceProducer { webrender_surfman, gl, size })
+    }
+
+    pub fn size(&self) -> Size2D<i32> {
+        self.size
+    }
+
+    /// Makes the context current with its surface bound, and returns the GL
+    /// bindings to draw with. The consumer's context has to be made current
+    /// again before webrender renders.
+    pub fn begin_frame(&self) -> Result<&dyn gl::Gl, Error> {
+        self.webrender_surfman.make_gl_context_current().map_err(Error::Context)?;
+        let framebuffer_object = self
+            .webrender_surfman
+            .context_surface_info()
+            .unwrap_or(None)
+            .map(|info| info.framebuffer_object)
+            .unwrap_or(0);
+        self.gl.bind_framebuffer(gl::FRAMEBUFFER, framebuffer_object);
+        self.gl.viewport(0, 0, self.size.width, self.size.height);
+        Ok(&*self.gl)
+    }
+
+    /// Hands what was drawn since `begin_frame` over to the consumer.
+    pub fn present(&self) -> Result<(), Error> {
+        self.gl.flush();
+        self.webrender_surfman.present().map_err(Error::Present)
+    }
+
+    /// The chain the frames are presented to.
+    pub fn swap_chain(&self) -> SwapChain<Device> {
+        self.webrender_surfman
+            .swap_chain()
+            .expect("Producers render into a generic surface")
+            .clone()
+    }
+}
@@ -0,0 +1,211 @@
+//! Text layout. Runs of text are shaped with rustybuzz, which applies the
+//! font's GSUB substitutions, e.g. ligatures and contextual forms, and its
+//! GPOS or `kern` positioning. Rusttype provides the vertical metrics. Lines
+//! are wrapped in the order the glyphs come out of shaping, which is only
+//! right for left to right text.
+
+use std::sync::Arc;
+use webrender::api::{GlyphInstance, units::{LayoutPoint, LayoutSize}};
+
+/// A parsed TrueType/OpenType font, used to lay out text for webrender.
+pub struct Font {
+    bytes: Arc<[u8]>,
+    index: u32,
+    metrics: rusttype::Font<'static>,
+}
+
+impl Font {
+    /// Parses the font at `index` of a font file or collection.
+    pub fn from_bytes(bytes: Vec<u8>, index: u32) -> Option<Font> {
+        let bytes: Arc<[u8]> = bytes.into();
+        rustybuzz::Face::from_slice(&bytes, index)?;
+        let metrics = rusttype::FontCollection::from_bytes(bytes.clone())
+            .and_then(|collection| collection.font_at(index as usize))
+            .ok()?;
+        Some(Font { bytes, index, metrics })
+    }
+
+    /// Rusttype scales by the pixel height of the font, webrender by the em size.
+    fn scale(&self, size: f32) -> rusttype::Scale {
+        let v_metrics = self.metrics.v_metrics_unscaled();
+        let units_per_em = self.metrics.units_per_em().max(1) as f32;
+        rusttype::Scale::uniform(size * (v_metrics.ascent - v_metrics.descent) / units_per_em)
+    }
+
+    /// The glyphs of a run of text with their positions in font units.
+    fn shape(&self, text: &str) -> rustybuzz::GlyphBuffer {
+        // `from_bytes` parsed the face already.
+        let face = rustybuzz::Face::from_slice(&self.bytes, self.index).unwrap();
+        let mut buffer = rustybuzz::UnicodeBuffer::new();
+        buffer.push_str(text);
+        buffer.guess_segment_properties();
+        rustybuzz::shape(&face, &[], buffer)
+    }
+}
+
+/// Glyphs of a string, shaped and wrapped to a maximum width.
+pub struct TextLayout {
+    /// Positioned relative to the top left corner of the text.
+    pub glyphs: Vec<GlyphInstance>,
+    /// The extent of the laid out lines.
+    pub size: LayoutSize,
+}
+
+impl TextLayout {
+    /// Lays out `text` at `size` (the size of the font instance the glyphs
+    /// are drawn with). Lines break at `\n` and, when they would get wider
+    /// than `max_width`, after the last whitespace or before the overflowing
+    /// glyph if the line has no whitespace.
+    pub fn new(font: &Font, text: &str, size: f32, max_width: f32) -> TextLayout {
+        let v_metrics = font.metrics.v_metrics(font.scale(size));
+        let line_height = v_metrics.ascent - v_metrics.descent + v_metrics.line_gap;
+        let units_to_pixels = size / font.metrics.units_per_em().max(1) as f32;
+
+        let mut glyphs: Vec<GlyphInstance> = Vec::with_capacity(text.len());
+        // Where the pen was for each glyph, which differs from where the
+        // glyph is drawn by the offsets of GPOS, e.g. for marks.
+        let mut pens: Vec<f32> = Vec::with_capacity(text.len());
+        let mut baseline = v_metrics.ascent;
+        let mut width: f32 = 0.0;
+
+        for paragraph in text.split('\n') {
+            let paragraph: String = paragraph.chars().filter(|ch| !ch.is_control()).collect();
+            let shaped = font.shape(&paragraph);
+            let mut x = 0.0;
+            // Index of the first glyph after the last whitespace of the line.
+            let mut break_at = None;
+            let mut line_start = glyphs.len();
+
+            for (info, position) in shaped.glyph_infos().iter().zip(shaped.glyph_positions()) {
+                // Clusters are byte offsets of the characters a glyph shows.
+                let character = paragraph[info.cluster as usize..].chars().next();
+                let whitespace = matches!(character, Some(ch) if ch.is_whitespace());
+                let advance = position.x_advance as f32 * units_to_pixels;
+
+                if !whitespace && x + advance > max_width && glyphs.len() > line_start {
+                    // Move what follows the break opportunity to a new line.
+                    let carry_from = break_at.unwrap_or(glyphs.len());
+                    let dx = pens.get(carry_from).copied().unwrap_or(x);
+                    // The whitespace the line breaks at doesn't count.
+                    let line_width = match break_at {
+                        Some(_) => pens[carry_from - 1],
+                        None => dx,
+                    };
+                    width = width.max(line_width);
+                    for (glyph, pen) in glyphs[carry_from..].iter_mut().zip(&mut pens[carry_from..]) {
+                        glyph.point.x -= dx;
+                        glyph.point.y += line_height;
+                        *pen -= dx;
+                    }
+                    x -= dx;
+                    baseline += line_height;
+                    line_start = carry_from;
+                    break_at = None;
+                }
+
+                glyphs.push(GlyphInstance {
+                    index: info.glyph_id,
+                    point: LayoutPoint::new(
+                        x + position.x_offset as f32 * units_to_pixels,
+                        baseline - position.y_offset as f32 * units_to_pixels,
+                    ),
+                });
+                pens.push(x);
+                x += advance;
+                if whitespace {
+                    break_at = Some(glyphs.len());
+                }
+            }
+
+            width = width.max(x);
+            baseline += line_height;
+        }
+
+        let height = baseline - line_height - v_metrics.descent;
+        TextLayout {
+            glyphs,
+            size: LayoutSize::new(width, height),
+        }
+    }
+
+    /// The glyphs with the top left corner of the text at `origin`.
+    pub fn glyphs_at(&self, origin: LayoutPoint) -> Vec<GlyphInstance> {
+        self.glyphs
+            .iter()
+            .map(|glyph| GlyphInstance {
+                index: glyph.index,
+                point: origin + glyph.point.to_vector(),
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn font() -> Font {
+        Font::from_bytes(fs::read("res/fonts/FreeSans.ttf").unwrap(), 0).unwrap()
+    }
+
+    /// The number of glyphs on each line.
+    fn line_lengths(layout: &TextLayout) -> Vec<usize> {
+        let mut lengths: Vec<(f32, usize)> = Vec::new();
+        for glyph in &layout.glyphs {
+            match lengths.last_mut() {
+                Some((y, length)) if *y == glyph.point.y => *length += 1,
+                _ => lengths.push((glyph.point.y, 1)),
+            }
+        }
+        lengths.into_iter().map(|(_, length)| length).collect()
+    }
+
+    #[test]
+    fn lays_out_a_line_that_fits() {
+        let layout = TextLayout::new(&font(), "aaa bbb", 32.0, 1000.0);
+        assert_eq!(line_lengths(&layout), [7]);
+        assert_eq!(layout.glyphs[0].point.x, 0.0);
+        assert!(layout.glyphs.windows(2).all(|pair| pair[0].point.x < pair[1].point.x));
+        assert!(layout.size.width > layout.glyphs[6].point.x);
+        assert!(layout.size.height > 0.0);
+    }
+
+    #[test]
+    fn wraps_after_the_last_whitespace() {
+        let font = font();
+        let line = TextLayout::new(&font, "aaa b", 32.0, f32::INFINITY);
+        let layout = TextLayout::new(&font, "aaa b", 32.0, line.size.width - 1.0);
+        assert_eq!(line_lengths(&layout), [4, 1]);
+        // The second line starts at the left edge, and the space the line
+        // breaks at doesn't count towards the width.
+        assert_eq!(layout.glyphs[4].point.x, 0.0);
+        assert_eq!(layout.size.width, line.glyphs[3].point.x);
+        assert!(layout.size.height > line.size.height);
+    }
+
+    #[test]
+    fn breaks_words_longer_than_the_width() {
+        let font = font();
+        let line = TextLayout::new(&font, "aaaaaa", 32.0, f32::INFINITY);
+        let layout = TextLayout::new(&font, "aaaaaa", 32.0, line.glyphs[3].point.x + 0.5);
+        assert_eq!(line_lengths(&layout), [3, 3]);
+        assert_eq!(layout.glyphs[3].point.x, 0.0);
+    }
+
+    #[test]
+    fn applies_the_kerning_of_the_font() {
+        let font = font();
+        let a = TextLayout::new(&font, "A", 32.0, f32::INFINITY);
+        let pair = TextLayout::new(&font, "AV", 32.0, f32::INFINITY);
+        assert!(pair.glyphs[1].point.x < a.size.width);
+    }
+
+    #[test]
+    fn breaks_lines_at_line_feeds() {
+        let layout = TextLayout::new(&font(), "a\nb\r", 32.0, 1000.0);
+        assert_eq!(line_lengths(&layout), [1, 1]);
+        assert_eq!(layout.glyphs[1].point.x, 0.0);
+        assert!(layout.glyphs[1].point.y > layout.glyphs[0].point.y);
+    }
+}
@@ -0,0 +1,922 @@
+use euclid::default::{Point2D, Size2D, Vector2D};
+use std::{collections::HashMap, f32::consts::PI, sync::{Arc, Mutex}};
+use webrender::api::{
+    AsyncBlobImageRasterizer, BlobDirtyRect, BlobImageData, BlobImageError, BlobImageHandler,
+    BlobImageKey, BlobImageParams, BlobImageRequest, BlobImageResources, BlobImageResult,
+    ColorF, FontInstanceKey, FontKey, GradientStop, IdNamespace, ImageFormat,
+    RasterizedBlobImage, TileSize,
+    units::{DeviceIntPoint, DeviceIntRect, DeviceIntSize}
+};
+
+/// The size of the tiles vector images are rasterized in.
+pub const TILE_SIZE: TileSize = 256;
+
+type Point = Point2D<f32>;
+
+#[derive(Clone, Copy, Debug)]
+enum Segment {
+    MoveTo(Point),
+    LineTo(Point),
+    QuadTo(Point, Point),
+    CubicTo(Point, Point, Point),
+    Close,
+}
+
+/// An outline of lines and Bézier curves. Each `move_to` starts a subpath.
+#[derive(Clone, Debug, Default)]
+pub struct VectorPath {
+    segments: Vec<Segment>,
+}
+
+impl VectorPath {
+    pub fn new() -> Self {
+        VectorPath::default()
+    }
+
+    pub fn move_to(mut self, x: f32, y: f32) -> Self {
+        self.segments.push(Segment::MoveTo(Point::new(x, y)));
+        self
+    }
+
+    pub fn line_to(mut self, x: f32, y: f32) -> Self {
+        self.segments.push(Segment::LineTo(Point::new(x, y)));
+        self
+    }
+
+    /// A quadratic curve through the control point `(cx, cy)`.
+    pub fn quad_to(mut self, cx: f32, cy: f32, x: f32, y: f32) -> Self {
+        self.segments.push(Segment::QuadTo(Point::new(cx, cy), Point::new(x, y)));
+        self
+    }
+
+    /// A cubic curve through the control points `(c1x, c1y)` and `(c2x, c2y)`.
+    pub fn cubic_to(mut self, c1x: f32, c1y: f32, c2x: f32, c2y: f32, x: f32, y: f32) -> Self {
+        self.segments.push(Segment::CubicTo(Point::new(c1x, c1y), Point::new(c2x, c2y), Point::new(x, y)));
+        self
+    }
+
+    /// Joins the end of the subpath to its start.
+    pub fn close(mut self) -> Self {
+        self.segments.push(Segment::Close);
+        self
+    }
+
+    pub fn rect(x: f32, y: f32, width: f32, height: f32) -> Self {
+        VectorPath::new()
+            .move_to(x, y)
+            .line_to(x + width, y)
+            .line_to(x + width, y + height)
+            .line_to(x, y + height)
+            .close()
+    }
+
+    /// A circle made of four cubic curves.
+    pub fn circle(cx: f32, cy: f32, radius: f32) -> Self {
+        // The distance of the control points that best approximates a
+        // quarter circle.
+        let k = radius * 0.552_284_8;
+        VectorPath::new()
+            .move_to(cx + radius, cy)
+            .cubic_to(cx + radius, cy + k, cx + k, cy + radius, cx, cy + radius)
+            .cubic_to(cx - k, cy + radius, cx - radius, cy + k, cx - radius, cy)
+            .cubic_to(cx - radius, cy - k, cx - k, cy - radius, cx, cy - radius)
+            .cubic_to(cx + k, cy - radius, cx + radius, cy - k, cx + radius, cy)
+            .close()
+    }
+
+    /// The subpaths as polylines through the points `transform` maps them
+    /// to, with whether each is closed.
+    fn flatten<F: Fn(Point) -> Point>(&self, transform: F) -> Vec<(Vec<Point>, bool)> {
+        let mut subpaths = Vec::new();
+        let mut points = Vec::new();
+        let mut closed = false;
+        let mut current = transform(Point::zero());
+        for segment in &self.segments {
+            match *segment {
+                Segment::MoveTo(to) => {
+                    end_subpath(&mut subpaths, &mut points, &mut closed);
+                    current = transform(to);
+                    points.push(current);
+                    continue;
+                }
+                Segment::Close => {
+                    // Drawing on after a close starts where the subpath started.
+                    current = points.first().cloned().unwrap_or(current);
+                    closed = true;
+                    continue;
+                }
+                _ => {}
+            }
+            if closed || points.is_empty() {
+                end_subpath(&mut subpaths, &mut points, &mut closed);
+                points.push(current);
+            }
+            let from = current;
+            match *segment {
+                Segment::LineTo(to) => points.push(transform(to)),
+                Segment::QuadTo(control, to) => {
+                    let (control, to) = (transform(control), transform(to));
+                    let steps = curve_steps(&[from, control, to]);
+                    points.extend((1..=steps).map(|step| {
+                        let t = step as f32 / steps as f32;
+                        let mt = 1.0 - t;
+                        (from.to_vector() * (mt * mt)
+                            + control.to_vector() * (2.0 * mt * t)
+                            + to.to_vector() * (t * t))
+                            .to_point()
+                    }));
+                }
+                Segment::CubicTo(control1, control2, to) => {
+                    let (control1, control2, to) = (transform(control1), transform(control2), transform(to));
+                    let steps = curve_steps(&[from, control1, control2, to]);
+                    points.extend((1..=steps).map(|step| {
+                        let t = step as f32 / steps as f32;
+                        let mt = 1.0 - t;
+                        (from.to_vector() * (mt * mt * mt)
+                            + control1.to_vector() * (3.0 * mt * mt * t)
+                            + control2.to_vector() * (3.0 * mt * t * t)
+                            + to.to_vector() * (t * t * t))
+                            .to_point()
+                    }));
+                }
+                Segment::MoveTo(_) | Segment::Close => unreachable!(),
+            }
+            current = *points.last().unwrap();
+        }
+        end_subpath(&mut subpaths, &mut points, &mut closed);
+        subpaths
+    }
+}
+
+/// Moves the points of a subpath to `subpaths`, unless it has no segments.
+fn end_subpath(subpaths: &mut Vec<(Vec<Point>, bool)>, points: &mut Vec<Point>, closed: &mut bool) {
+    if points.len() > 1 {
+        subpaths.push((std::mem::take(points), *closed));
+    }
+    points.clear();
+    *closed = false;
+}
+
+/// Line segments enough for a curve through `points` to look smooth, from
+/// the length of its control polygon in pixels.
+fn curve_steps(points: &[Point]) -> u32 {
+    let length: f32 = points.windows(2).map(|pair| (pair[1] - pair[0]).length()).sum();
+    (length / 4.0).ceil().clamp(1.0, 256.0) as u32
+}
+
+/// How the inside of a path is colored. Gradient points are in the
+/// coordinates of the path, colors are padded beyond the stops.
+#[derive(Clone, Debug)]
+pub enum Paint {
+    Solid(ColorF),
+    LinearGradient { start: Point, end: Point, stops: Vec<GradientStop> },
+    RadialGradient { center: Point, radius: f32, stops: Vec<GradientStop> },
+}
+
+impl Paint {
+    /// The premultiplied color at `point`.
+    fn color_at(&self, point: Point) -> [f32; 4] {
+        let (t, stops) = match *self {
+            Paint::Solid(color) => return premultiply(color),
+            Paint::LinearGradient { start, end, ref stops } => {
+                let direction = end - start;
+                let length = direction.square_length();
+                let t = if length > 0.0 { (point - start).dot(direction) / length } else { 0.0 };
+                (t, stops)
+            }
+            Paint::RadialGradient { center, radius, ref stops } => {
+                let t = if radius > 0.0 { (point - center).length() / radius } else { 0.0 };
+                (t, stops)
+            }
+        };
+        let (first, last) = match (stops.first(), stops.last()) {
+            (Some(first), Some(last)) => (first, last),
+            _ => return [0.0; 4],
+        };
+        if t <= first.offset {
+            return premultiply(first.color);
+        }
+        for pair in stops.windows(2) {
+            let (from, to) = (&pair[0], &pair[1]);
+            if t <= to.offset {
+                let span = to.offset - from.offset;
+                let f = if span > 0.0 { (t - from.offset) / span } else { 1.0 };
+                let (from, to) = (premultiply(from.color), premultiply(to.color));
+                return [
+                    from[0] + (to[0] - from[0]) * f,
+                    from[1] + (to[1] - from[1]) * f,
+                    from[2] + (to[2] - from[2]) * f,
+                    from[3] + (to[3] - from[3]) * f,
+                ];
+            }
+        }
+        premultiply(last.color)
+    }
+}
+
+fn premultiply(color: ColorF) -> [f32; 4] {
+    [color.r * color.a, color.g * color.a, color.b * color.a, color.a]
+}
+
+#[derive(Clone, Debug)]
+enum Command {
+    Fill(VectorPath, Paint),
+    Stroke(VectorPath, f32, Paint),
+}
+
+/// Paths filled and stroked in order, in the coordinates of a view box that
+/// is stretched over the image. Shown with `Compositor::add_vector_image`,
+/// it is rasterized at the size of the image, so it stays sharp at any
+/// scale.
+#[derive(Clone, Debug)]
+pub struct VectorImage {
+    view_box: Size2D<f32>,
+    commands: Vec<Command>,
+}
+
+impl VectorImage {
+    pub fn new(width: f32, height: f32) -> Self {
+        VectorImage {
+            view_box: Size2D::new(width, height),
+            commands: Vec::new(),
+        }
+    }
+
+    pub fn view_box(&self) -> Size2D<f32> {
+        self.view_box
+    }
+
+    /// Fills the inside of `path` by the nonzero rule, open subpaths are
+    /// closed.
+    pub fn fill(&mut self, path: VectorPath, paint: Paint) {
+        self.commands.push(Command::Fill(path, paint));
+    }
+
+    /// Strokes `path` with round joins and caps.
+    pub fn stroke(&mut self, path: VectorPath, width: f32, paint: Paint) {
+        self.commands.push(Command::Stroke(path, width, paint));
+    }
+
+    /// The blob data of the image rasterized at `size` pixels.
+    pub fn encode(&self, size: DeviceIntSize) -> BlobImageData {
+        let mut data = Vec::new();
+        put_u32(&mut data, size.width as u32);
+        put_u32(&mut data, size.height as u32);
+        put_f32(&mut data, self.view_box.width);
+        put_f32(&mut data, self.view_box.height);
+        for command in &self.commands {
+            match *command {
+                Command::Fill(ref path, ref paint) => {
+                    data.push(0);
+                    put_path(&mut data, path);
+                    put_paint(&mut data, paint);
+                }
+                Command::Stroke(ref path, width, ref paint) => {
+                    data.push(1);
+                    put_f32(&mut data, width);
+                    put_path(&mut data, path);
+                    put_paint(&mut data, paint);
+                }
+            }
+        }
+        data
+    }
+}
+
+fn put_u32(data: &mut Vec<u8>, value: u32) {
+    data.extend_from_slice(&value.to_le_bytes());
+}
+
+fn put_f32(data: &mut Vec<u8>, value: f32) {
+    data.extend_from_slice(&value.to_le_bytes());
+}
+
+fn put_point(data: &mut Vec<u8>, point: Point) {
+    put_f32(data, point.x);
+    put_f32(data, point.y);
+}
+
+fn put_color(data: &mut Vec<u8>, color: ColorF) {
+    for &value in &[color.r, color.g, color.b, color.a] {
+        put_f32(data, value);
+    }
+}
+
+fn put_path(data: &mut Vec<u8>, path: &VectorPath) {
+    put_u32(data, path.segments.len() as u32);
+    for segment in &path.segments {
+        match *segment {
+            Segment::MoveTo(to) => {
+                data.push(0);
+                put_point(data, to);
+            }
+            Segment::LineTo(to) => {
+                data.push(1);
+                put_point(data, to);
+            }
+            Segment::QuadTo(control, to) => {
+                data.push(2);
+                put_point(data, control);
+                put_point(data, to);
+            }
+            Segment::CubicTo(control1, control2, to) => {
+                data.push(3);
+                put_point(data, control1);
+                put_point(data, control2);
+                put_point(data, to);
+            }
+            Segment::Close => data.push(4),
+        }
+    }
+}
+
+fn put_paint(data: &mut Vec<u8>, paint: &Paint) {
+    let stops = match *paint {
+        Paint::Solid(color) => {
+            data.push(0);
+            put_color(data, color);
+            return;
+        }
+        Paint::LinearGradient { start, end, ref stops } => {
+            data.push(1);
+            put_point(data, start);
+            put_point(data, end);
+            stops
+        }
+        Paint::RadialGradient { center, radius, ref stops } => {
+            data.push(2);
+            put_point(data, center);
+            put_f32(data, radius);
+            stops
+        }
+    };
+    put_u32(data, stops.len() as u32);
+    for stop in stops {
+        put_f32(data, stop.offset);
+        put_color(data, stop.color);
+    }
+}
+
+/// Reads what `VectorImage::encode` wrote, `None` past the end.
+struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, count: usize) -> Option<&'a [u8]> {
+        if self.data.len() < count {
+            return None;
+        }
+        let (bytes, rest) = self.data.split_at(count);
+        self.data = rest;
+        Some(bytes)
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        self.bytes(1).map(|bytes| bytes[0])
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        self.bytes(4).map(|bytes| u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    fn f32(&mut self) -> Option<f32> {
+        self.u32().map(f32::from_bits)
+    }
+
+    fn point(&mut self) -> Option<Point> {
+        Some(Point::new(self.f32()?, self.f32()?))
+    }
+
+    fn color(&mut self) -> Option<ColorF> {
+        Some(ColorF::new(self.f32()?, self.f32()?, self.f32()?, self.f32()?))
+    }
+
+    fn path(&mut self) -> Option<VectorPath> {
+        let count = self.u32()?;
+        let mut segments = Vec::new();
+        for _ in 0..count {
+            segments.push(match self.u8()? {
+                0 => Segment::MoveTo(self.point()?),
+                1 => Segment::LineTo(self.point()?),
+                2 => Segment::QuadTo(self.point()?, self.point()?),
+                3 => Segment::CubicTo(self.point()?, self.point()?, self.point()?),
+                4 => Segment::Close,
+                _ => return None,
+            });
+        }
+        Some(VectorPath { segments })
+    }
+
+    fn paint(&mut self) -> Option<Paint> {
+        let tag = self.u8()?;
+        if tag == 0 {
+            return Some(Paint::Solid(self.color()?));
+        }
+        let (start, end, radius) = match tag {
+            1 => (self.point()?, self.point()?, 0.0),
+            2 => (self.point()?, Point::zero(), self.f32()?),
+            _ => return None,
+        };
+        let count = self.u32()?;
+        let mut stops = Vec::new();
+        for _ in 0..count {
+            stops.push(GradientStop { offset: self.f32()?, color: self.color()? });
+        }
+        Some(match tag {
+            1 => Paint::LinearGradient { start, end, stops },
+            _ => Paint::RadialGradient { center: start, radius, stops },
+        })
+    }
+}
+
+/// A `VectorImage` and the size it is rasterized at.
+struct Drawing {
+    size: DeviceIntSize,
+    image: VectorImage,
+}
+
+impl Drawing {
+    fn decode(data: &[u8]) -> Option<Drawing> {
+        let mut reader = Reader { data };
+        let size = DeviceIntSize::new(reader.u32()? as i32, reader.u32()? as i32);
+        let mut image = VectorImage::new(reader.f32()?, reader.f32()?);
+        while !reader.data.is_empty() {
+            let command = match reader.u8()? {
+                0 => Command::Fill(reader.path()?, reader.paint()?),
+                1 => {
+                    let width = reader.f32()?;
+                    Command::Stroke(reader.path()?, width, reader.paint()?)
+                }
+                _ => return None,
+            };
+            image.commands.push(command);
+        }
+        Some(Drawing { size, image })
+    }
+
+    /// Rasterizes the part of the image at `rect` into premultiplied BGRA8
+    /// pixels.
+    fn rasterize(&self, rect: DeviceIntRect) -> Vec<u8> {
+        let (width, height) = (rect.size.width as usize, rect.size.height as usize);
+        let view_box = self.image.view_box;
+        let scale = Vector2D::new(
+            self.size.width as f32 / view_box.width,
+            self.size.height as f32 / view_box.height,
+        );
+        let origin = Vector2D::new(rect.origin.x as f32, rect.origin.y as f32);
+        let to_tile = |point: Point| Point::new(point.x * scale.x, point.y * scale.y) - origin;
+
+        let mut pixels = vec![[0.0f32; 4]; width * height];
+        for command in &self.image.commands {
+            let (polygons, paint): (Vec<Vec<Point>>, _) = match *command {
+                Command::Fill(ref path, ref paint) => {
+                    let polygons = path.flatten(to_tile).into_iter().map(|(points, _)| points).collect();
+                    (polygons, paint)
+                }
+                Command::Stroke(ref path, stroke_width, ref paint) => {
+                    let half_width = stroke_width * (scale.x + scale.y) / 4.0;
+                    (stroke_polygons(&path.flatten(to_tile), half_width), paint)
+                }
+            };
+            if !intersects_tile(&polygons, width, height) {
+                continue;
+            }
+
+            let mut coverage = Coverage::new(width, height);
+            for polygon in &polygons {
+                for (index, &from) in polygon.iter().enumerate() {
+                    coverage.add_edge(from, polygon[(index + 1) % polygon.len()]);
+                }
+            }
+            coverage.for_each(|x, y, alpha| {
+                // Paints are sampled at pixel centers, in view box coordinates.
+                let point = Point::new(
+                    (x as f32 + 0.5 + origin.x) / scale.x,
+                    (y as f32 + 0.5 + origin.y) / scale.y,
+                );
+                let color = paint.color_at(point);
+                let pixel = &mut pixels[y * width + x];
+                let remaining = 1.0 - color[3] * alpha;
+                for channel in 0..4 {
+                    pixel[channel] = color[channel] * alpha + pixel[channel] * remaining;
+                }
+            });
+        }
+
+        let to_u8 = |value: f32| (value.clamp(0.0, 1.0) * 255.0).round() as u8;
+        let mut data = Vec::with_capacity(width * height * 4);
+        for &[r, g, b, a] in &pixels {
+            data.extend_from_slice(&[to_u8(b), to_u8(g), to_u8(r), to_u8(a)]);
+        }
+        data
+    }
+}
+
+/// Whether the bounds of `polygons` overlap a tile of `width` by `height`.
+fn intersects_tile(polygons: &[Vec<Point>], width: usize, height: usize) -> bool {
+    let mut points = polygons.iter().flatten();
+    let first = match points.next() {
+        Some(first) => *first,
+        None => return false,
+    };
+    let (mut min, mut max) = (first, first);
+    for point in points {
+        min = Point::new(min.x.min(point.x), min.y.min(point.y));
+        max = Point::new(max.x.max(point.x), max.y.max(point.y));
+    }
+    min.x < width as f32 && min.y < height as f32 && max.x > 0.0 && max.y > 0.0
+}
+
+/// The outline of strokes along `subpaths`, as quads along the lines and
+/// circles around the points where they join and end. All wind the same way
+/// so that filling them by the nonzero rule covers their union.
+fn stroke_polygons(subpaths: &[(Vec<Point>, bool)], half_width: f32) -> Vec<Vec<Point>> {
+    let mut polygons = Vec::new();
+    for (points, closed) in subpaths {
+        let mut points = points.clone();
+        if *closed && points.len() > 1 {
+            points.push(points[0]);
+        }
+        for pair in points.windows(2) {
+            let (from, to) = (pair[0], pair[1]);
+            if from == to {
+                continue;
+            }
+            let along = (to - from).normalize() * half_width;
+            let across = Vector2D::new(-along.y, along.x);
+            polygons.push(wind(vec![from + across, to + across, to - across, from - across]));
+        }
+        for &point in &points {
+            let steps = (half_width * 2.0).ceil().clamp(8.0, 64.0) as usize;
+            let circle = (0..steps)
+                .map(|step| {
+                    let angle = step as f32 / steps as f32 * 2.0 * PI;
+                    point + Vector2D::new(angle.cos(), angle.sin()) * half_width
+                })
+                .collect();
+            polygons.push(wind(circle));
+        }
+    }
+    polygons
+}
+
+/// Reverses `polygon` if needed so that its signed area is positive.
+fn wind(mut polygon: Vec<Point>) -> Vec<Point> {
+    let area: f32 = polygon
+        .iter()
+        .zip(polygon.iter().cycle().skip(1))
+        .map(|(a, b)| a.x * b.y - b.x * a.y)
+        .sum();
+    if area < 0.0 {
+        polygon.reverse();
+    }
+    polygon
+}
+
+/// The coverage of a tile by polygons, accumulated as the signed area each
+/// edge adds to the pixels of a row right of it. Every row has two extra
+/// cells for the edges that end on the right side.
+struct Coverage {
+    width: usize,
+    height: usize,
+    area: Vec<f32>,
+}
+
+impl Coverage {
+    fn new(width: usize, height: usize) -> Self {
+        Coverage { width, height, area: vec![0.0; (width + 2) * height] }
+    }
+
+    /// Adds an edge, splitting it where it crosses the left and right sides
+    /// of the tile. The parts outside are moved onto the sides, where they
+    /// still count for the rows they cross.
+    fn add_edge(&mut self, from: Point, to: Point) {
+        let right = self.width as f32;
+        let mut splits = vec![0.0, 1.0];
+        for &side in &[0.0, right] {
+            let t = (side - from.x) / (to.x - from.x);
+            if t > 0.0 && t < 1.0 {
+                splits.push(t);
+            }
+        }
+        splits.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        let point_at = |t: f32| {
+            let point = from + (to - from) * t;
+            Point::new(point.x.max(0.0).min(right), point.y)
+        };
+        for pair in splits.windows(2) {
+            self.add_line(point_at(pair[0]), point_at(pair[1]));
+        }
+    }
+
+    /// Adds a line between the left and right sides, row by row.
+    fn add_line(&mut self, from: Point, to: Point) {
+        if (from.y - to.y).abs() < f32::EPSILON {
+            return;
+        }
+        let (direction, top, bottom) = if from.y < to.y { (1.0, from, to) } else { (-1.0, to, from) };
+        let right = self.width as f32;
+        let stride = self.width + 2;
+        let dxdy = (bottom.x - top.x) / (bottom.y - top.y);
+        let mut x = top.x;
+        if top.y < 0.0 {
+            x -= top.y * dxdy;
+        }
+        let first_row = top.y.max(0.0) as usize;
+        let end_row = (bottom.y.ceil().max(0.0) as usize).min(self.height);
+        for row in first_row..end_row {
+            let line = row * stride;
+            let dy = ((row + 1) as f32).min(bottom.y) - (row as f32).max(top.y);
+            let x_next = (x + dxdy * dy).max(0.0).min(right);
+            let d = dy * direction;
+            let (x0, x1) = if x < x_next { (x, x_next) } else { (x_next, x) };
+            let x0_floor = x0.floor();
+            let x1_ceil = x1.ceil();
+            let (x0i, x1i) = (x0_floor as usize, x1_ceil as usize);
+            if x1i <= x0i + 1 {
+                // Within a pixel, split between it and the next.
+                let middle = 0.5 * (x + x_next) - x0_floor;
+                self.area[line + x0i] += d - d * middle;
+                self.area[line + x0i + 1] += d * middle;
+            } else {
+                let s = (x1 - x0).recip();
+                let x0_fract = x0 - x0_floor;
+                let a0 = 0.5 * s * (1.0 - x0_fract) * (1.0 - x0_fract);
+                let x1_fract = x1 - x1_ceil + 1.0;
+                let am = 0.5 * s * x1_fract * x1_fract;
+                self.area[line + x0i] += d * a0;
+                if x1i == x0i + 2 {
+                    self.area[line + x0i + 1] += d * (1.0 - a0 - am);
+                } else {
+                    let a1 = s * (1.5 - x0_fract);
+                    self.area[line + x0i + 1] += d * (a1 - a0);
+                    for xi in x0i + 2..x1i - 1 {
+                        self.area[line + xi] += d * s;
+                    }
+                    let a2 = a1 + (x1i - x0i - 3) as f32 * s;
+                    self.area[line + x1i - 1] += d * (1.0 - a2 - am);
+                }
+                self.area[line + x1i] += d * am;
+            }
+            x = x_next;
+        }
+    }
+
+    /// Calls `f` with each covered pixel and how much of it is covered.
+    fn for_each<F: FnMut(usize, usize, f32)>(&self, mut f: F) {
+        for (y, row) in self.area.chunks(self.width + 2).enumerate() {
+            let mut accumulated = 0.0;
+            for (x, area) in row[..self.width].iter().enumerate() {
+                accumulated += area;
+                let alpha = accumulated.abs().min(1.0);
+                if alpha > 1.0 / 512.0 {
+                    f(x, y, alpha);
+                }
+            }
+        }
+    }
+}
+
+/// Generates the pixels of an image as webrender asks for its tiles, see
+/// `Compositor::add_generated_image`. Called on webrender's threads.
+pub trait TileSource: Send + Sync {
+    /// The premultiplied BGRA8 pixels of `rect` of the image, top row first.
+    fn generate(&self, rect: DeviceIntRect) -> Vec<u8>;
+}
+
+/// The tile sources of generated images, shared by the compositor adding
+/// them and the handler rasterizing them.
+pub type TileSources = Arc<Mutex<HashMap<BlobImageKey, Arc<dyn TileSource>>>>;
+
+/// Rasterizes the `VectorImage`s of blob images on the CPU, and generates
+/// the tiles of the images of `TileSources`. `app::run` installs it unless
+/// the app's `RendererOptions` come with a blob image handler.
+#[derive(Default)]
+pub struct VectorImageHandler {
+    /// The decoded images, `None` for blob data that failed to decode.
+    drawings: HashMap<BlobImageKey, Option<Arc<Drawing>>>,
+    tile_sources: TileSources,
+}
+
+impl VectorImageHandler {
+    pub fn new(tile_sources: TileSources) -> Self {
+        VectorImageHandler {
+            drawings: HashMap::new(),
+            tile_sources,
+        }
+    }
+
+    fn set(&mut self, key: BlobImageKey, data: &BlobImageData) {
+        // Generated images have no blob data to decode.
+        if self.tile_sources.lock().unwrap().contains_key(&key) {
+            return;
+        }
+        let drawing = Drawing::decode(data);
+        if drawing.is_none() {
+            println!("Failed to decode vector image {:?}", key);
+        }
+        self.drawings.insert(key, drawing.map(Arc::new));
+    }
+}
+
+impl BlobImageHandler for VectorImageHandler {
+    fn create_blob_rasterizer(&mut self) -> Box<dyn AsyncBlobImageRasterizer> {
+        Box::new(VectorImageRasterizer {
+            drawings: self.drawings.clone(),
+            tile_sources: self.tile_sources.lock().unwrap().clone(),
+        })
+    }
+
+    fn create_similar(&self) -> Box<dyn BlobImageHandler> {
+        Box::new(VectorImageHandler::new(self.tile_sources.clone()))
+    }
+
+    fn prepare_resources(&mut self, _: &dyn BlobImageResources, _: &[BlobImageParams]) {}
+
+    fn add(&mut self, key: BlobImageKey, data: Arc<BlobImageData>, _: &DeviceIntRect, _: TileSize) {
+        self.set(key, &data);
+    }
+
+    // Vector images are rasterized whole, the dirty rect doesn't matter.
+    fn update(&mut self, key: BlobImageKey, data: Arc<BlobImageData>, _: &DeviceIntRect, _: &BlobDirtyRect) {
+        self.set(key, &data);
+    }
+
+    fn delete(&mut self, key: BlobImageKey) {
+        self.drawings.remove(&key);
+    }
+
+    fn delete_font(&mut self, _: FontKey) {}
+
+    fn delete_font_instance(&mut self, _: FontInstanceKey) {}
+
+    fn clear_namespace(&mut self, namespace: IdNamespace) {
+        self.drawings.retain(|key, _| (key.0).0 != namespace);
+    }
+
+    fn enable_multithreading(&mut self, _: bool) {}
+}
+
+/// The images of the handler at the time webrender asked for tiles.
+struct VectorImageRasterizer {
+    drawings: HashMap<BlobImageKey, Option<Arc<Drawing>>>,
+    tile_sources: HashMap<BlobImageKey, Arc<dyn TileSource>>,
+}
+
+impl VectorImageRasterizer {
+    fn rasterize_tile(&self, params: &BlobImageParams) -> BlobImageResult {
+        if params.descriptor.format != ImageFormat::BGRA8 {
+            return Err(BlobImageError::Other(format!("Unsupported format {:?}", params.descriptor.format)));
+        }
+        let rect = params.descriptor.rect;
+        let tile_rect = DeviceIntRect::new(
+            DeviceIntPoint::new(rect.origin.x, rect.origin.y),
+            DeviceIntSize::new(rect.size.width, rect.size.height),
+        );
+        let key = params.request.key;
+        let data = match (self.drawings.get(&key), self.tile_sources.get(&key)) {
+            (Some(Some(drawing)), _) => drawing.rasterize(tile_rect),
+            (_, Some(source)) => source.generate(tile_rect),
+            (Some(None), None) => return Err(BlobImageError::InvalidData),
+            (None, None) => return Err(BlobImageError::InvalidKey),
+        };
+        // The whole tile is rasterized, with no offset or stride.
+        Ok(RasterizedBlobImage {
+            rasterized_rect: DeviceIntRect::new(DeviceIntPoint::zero(), tile_rect.size),
+            data: Arc::new(data),
+        })
+    }
+}
+
+impl AsyncBlobImageRasterizer for VectorImageRasterizer {
+    fn rasterize(
+        &mut self,
+        requests: &[BlobImageParams],
+        _low_priority: bool,
+    ) -> Vec<(BlobImageRequest, BlobImageResult)> {
+        requests
+            .iter()
+            .map(|params| (params.request, self.rasterize_tile(params)))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WHITE: ColorF = ColorF { r: 1.0, g: 1.0, b: 1.0, a: 1.0 };
+
+    fn drawing(image: VectorImage, width: i32, height: i32) -> Drawing {
+        Drawing { size: DeviceIntSize::new(width, height), image }
+    }
+
+    fn rasterize_all(drawing: &Drawing) -> Vec<u8> {
+        drawing.rasterize(DeviceIntRect::new(DeviceIntPoint::zero(), drawing.size))
+    }
+
+    /// The alpha of each pixel of `pixels`, `width` pixels a row.
+    fn alphas(pixels: &[u8], width: usize) -> Vec<Vec<u8>> {
+        let alphas: Vec<u8> = pixels.chunks(4).map(|pixel| pixel[3]).collect();
+        alphas.chunks(width).map(|row| row.to_vec()).collect()
+    }
+
+    fn coverage(polygon: &[Point], width: usize, height: usize) -> Vec<f32> {
+        let mut coverage = Coverage::new(width, height);
+        for (index, &from) in polygon.iter().enumerate() {
+            coverage.add_edge(from, polygon[(index + 1) % polygon.len()]);
+        }
+        let mut alphas = vec![0.0; width * height];
+        coverage.for_each(|x, y, alpha| alphas[y * width + x] = alpha);
+        alphas
+    }
+
+    #[test]
+    fn fills_whole_pixels_of_a_rect() {
+        let mut image = VectorImage::new(4.0, 4.0);
+        image.fill(VectorPath::rect(1.0, 1.0, 2.0, 2.0), Paint::Solid(WHITE));
+        let pixels = rasterize_all(&drawing(image, 4, 4));
+        assert_eq!(alphas(&pixels, 4), vec![
+            vec![0, 0, 0, 0],
+            vec![0, 255, 255, 0],
+            vec![0, 255, 255, 0],
+            vec![0, 0, 0, 0],
+        ]);
+        assert_eq!(&pixels[(4 + 1) * 4..(4 + 2) * 4], &[255, 255, 255, 255]);
+    }
+
+    #[test]
+    fn covers_half_of_a_pixel_an_edge_splits() {
+        let polygon = [Point::new(0.0, 0.0), Point::new(1.5, 0.0), Point::new(1.5, 1.0), Point::new(0.0, 1.0)];
+        assert_eq!(coverage(&polygon, 3, 1), vec![1.0, 0.5, 0.0]);
+
+        // A diagonal edge through a pixel covers half of it.
+        let triangle = [Point::new(0.0, 0.0), Point::new(1.0, 1.0), Point::new(0.0, 1.0)];
+        assert_eq!(coverage(&triangle, 1, 1), vec![0.5]);
+    }
+
+    #[test]
+    fn tiles_match_the_whole_image() {
+        let mut image = VectorImage::new(16.0, 16.0);
+        image.fill(VectorPath::circle(8.0, 8.0, 6.5), Paint::Solid(WHITE));
+        let drawing = drawing(image, 16, 16);
+        let whole = alphas(&rasterize_all(&drawing), 16);
+
+        for &(x, y) in &[(0, 0), (8, 0), (0, 8), (8, 8), (4, 4)] {
+            let tile = drawing.rasterize(DeviceIntRect::new(DeviceIntPoint::new(x, y), DeviceIntSize::new(8, 8)));
+            for (row, tile_row) in alphas(&tile, 8).iter().enumerate() {
+                let whole_row = &whole[y as usize + row][x as usize..x as usize + 8];
+                for (a, b) in tile_row.iter().zip(whole_row) {
+                    assert!(a.max(b) - a.min(b) <= 1, "tile at {:?}, row {}: {:?} != {:?}", (x, y), row, tile_row, whole_row);
+                }
+            }
+        }
+        // The center is covered, the corners are not.
+        assert_eq!(whole[8][8], 255);
+        assert_eq!(whole[0][0], 0);
+    }
+
+    #[test]
+    fn overlapping_stroke_polygons_cover_once() {
+        let mut image = VectorImage::new(8.0, 8.0);
+        let path = VectorPath::new().move_to(2.0, 4.0).line_to(6.0, 4.0).line_to(6.0, 2.0);
+        image.stroke(path, 2.0, Paint::Solid(ColorF::new(1.0, 1.0, 1.0, 0.5)));
+        let alphas = alphas(&rasterize_all(&drawing(image, 8, 8)), 8);
+        // Where the caps and the join overlap the lines, the stroke is as
+        // opaque as along them.
+        assert_eq!(alphas[3][2], 128);
+        assert_eq!(alphas[3][4], 128);
+        assert_eq!(alphas[3][5], 128);
+        assert_eq!(alphas[0][0], 0);
+        assert!(alphas.iter().flatten().all(|&alpha| alpha <= 128));
+    }
+
+    #[test]
+    fn decodes_what_it_encodes() {
+        let mut image = VectorImage::new(100.0, 50.0);
+        image.fill(
+            VectorPath::new().move_to(0.0, 0.0).quad_to(50.0, 0.0, 50.0, 50.0).cubic_to(20.0, 40.0, 10.0, 30.0, 0.0, 50.0).close(),
+            Paint::LinearGradient {
+                start: Point::new(0.0, 0.0),
+                end: Point::new(100.0, 0.0),
+                stops: vec![
+                    GradientStop { offset: 0.0, color: WHITE },
+                    GradientStop { offset: 1.0, color: ColorF::new(0.0, 0.0, 1.0, 0.5) },
+                ],
+            },
+        );
+        image.stroke(
+            VectorPath::circle(50.0, 25.0, 20.0),
+            3.0,
+            Paint::RadialGradient { center: Point::new(50.0, 25.0), radius: 20.0, stops: Vec::new() },
+        );
+        let size = DeviceIntSize::new(200, 100);
+        let data = image.encode(size);
+
+        let decoded = Drawing::decode(&data).unwrap();
+        assert_eq!(decoded.size, size);
+        assert_eq!(format!("{:?}", decoded.image), format!("{:?}", image));
+        assert!(Drawing::decode(&data[..data.len() - 1]).is_none());
+    }
+}
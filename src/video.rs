@@ -0,0 +1,754 @@
+use std::{
+    fmt, fs::File,
+    io::{self, BufRead, BufReader, Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
+    time::Duration
+};
+use webrender::api::{ColorDepth, ColorRange, ImageFormat, ImageKey, YuvColorSpace, YuvData};
+
+/// The largest width or height of a video, far beyond any texture size, so
+/// chroma sizes can't overflow.
+const MAX_FRAME_DIMENSION: i32 = 1 << 16;
+
+/// How the samples of a frame are laid out in memory. Samples of more than
+/// 8 bits take a little endian word in files and a native endian one in
+/// frames.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum VideoFormat {
+    /// Y, U and V planes, U and V at half the width and height.
+    I420,
+    /// A Y plane and a plane of interleaved U and V samples at half the width
+    /// and height.
+    NV12,
+    /// I420 with 10 bit samples in the low bits of words.
+    I420P10,
+    /// I420 with 12 bit samples in the low bits of words.
+    I420P12,
+    /// I420 with 16 bit samples.
+    I420P16,
+    /// NV12 with 10 bit samples in the high bits of words.
+    P010,
+    /// Y, U, Y and V samples of each pair of pixels interleaved, U and V at
+    /// half the width. Frames hold a V, Y, U texel per pixel, which is how
+    /// webrender samples interleaved images.
+    YUY2,
+}
+
+/// The size and texel format of one plane of a frame.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PlaneLayout {
+    pub width: i32,
+    pub height: i32,
+    pub format: ImageFormat,
+}
+
+impl PlaneLayout {
+    /// The bytes of the plane, `None` if they don't fit in memory.
+    pub fn data_len(&self) -> Option<usize> {
+        (self.width as usize)
+            .checked_mul(self.height as usize)?
+            .checked_mul(self.format.bytes_per_pixel() as usize)
+    }
+}
+
+impl VideoFormat {
+    pub const ALL: [VideoFormat; 7] = [
+        VideoFormat::I420,
+        VideoFormat::NV12,
+        VideoFormat::I420P10,
+        VideoFormat::I420P12,
+        VideoFormat::I420P16,
+        VideoFormat::P010,
+        VideoFormat::YUY2,
+    ];
+
+    /// The name in `--raw` layouts.
+    pub fn name(self) -> &'static str {
+        match self {
+            VideoFormat::I420 => "i420",
+            VideoFormat::NV12 => "nv12",
+            VideoFormat::I420P10 => "i420p10",
+            VideoFormat::I420P12 => "i420p12",
+            VideoFormat::I420P16 => "i420p16",
+            VideoFormat::P010 => "p010",
+            VideoFormat::YUY2 => "yuy2",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        VideoFormat::ALL.iter().copied().find(|format| format.name() == name)
+    }
+
+    /// The planes of a frame of `width` by `height` pixels, in the order
+    /// they are stored.
+    pub fn planes(self, width: i32, height: i32) -> Vec<PlaneLayout> {
+        let (chroma_width, chroma_height) = ((width + 1) / 2, (height + 1) / 2);
+        let plane = |width, height, format| PlaneLayout { width, height, format };
+        match self {
+            VideoFormat::I420 => vec![
+                plane(width, height, ImageFormat::R8),
+                plane(chroma_width, chroma_height, ImageFormat::R8),
+                plane(chroma_width, chroma_height, ImageFormat::R8),
+            ],
+            VideoFormat::NV12 => vec![
+                plane(width, height, ImageFormat::R8),
+                plane(chroma_width, chroma_height, ImageFormat::RG8),
+            ],
+            VideoFormat::I420P10 | VideoFormat::I420P12 | VideoFormat::I420P16 => vec![
+                plane(width, height, ImageFormat::R16),
+                plane(chroma_width, chroma_height, ImageFormat::R16),
+                plane(chroma_width, chroma_height, ImageFormat::R16),
+            ],
+            VideoFormat::P010 => vec![
+                plane(width, height, ImageFormat::R16),
+                plane(chroma_width, chroma_height, ImageFormat::RG16),
+            ],
+            VideoFormat::YUY2 => vec![plane(width, height, ImageFormat::RGBA8)],
+        }
+    }
+
+    /// The planes as files store them, which only differs from `planes` for
+    /// YUY2, four bytes for each pair of pixels.
+    fn stored_planes(self, width: i32, height: i32) -> Vec<PlaneLayout> {
+        match self {
+            VideoFormat::YUY2 => vec![PlaneLayout { width: (width + 1) / 2, height, format: ImageFormat::RGBA8 }],
+            _ => self.planes(width, height),
+        }
+    }
+
+    /// The yuv image of the images holding the planes, in order.
+    pub fn yuv_data(self, planes: &[ImageKey]) -> YuvData {
+        match self {
+            VideoFormat::I420 | VideoFormat::I420P10 | VideoFormat::I420P12 | VideoFormat::I420P16 => {
+                YuvData::PlanarYCbCr(planes[0], planes[1], planes[2])
+            }
+            VideoFormat::NV12 | VideoFormat::P010 => YuvData::NV12(planes[0], planes[1]),
+            VideoFormat::YUY2 => YuvData::InterleavedYCbCr(planes[0]),
+        }
+    }
+
+    /// How webrender scales samples up to the range of their texels.
+    pub fn color_depth(self) -> ColorDepth {
+        match self {
+            VideoFormat::I420P10 => ColorDepth::Color10,
+            VideoFormat::I420P12 => ColorDepth::Color12,
+            // The 10 bits of P010 are the high bits, so its words already
+            // span the full range.
+            VideoFormat::I420P16 | VideoFormat::P010 => ColorDepth::Color16,
+            VideoFormat::I420 | VideoFormat::NV12 | VideoFormat::YUY2 => ColorDepth::Color8,
+        }
+    }
+
+    /// The size of the chroma, half the width and for 4:2:0 half the height.
+    fn chroma_size(self, width: i32, height: i32) -> (usize, usize) {
+        let (width, height) = match self {
+            VideoFormat::YUY2 => ((width + 1) / 2, height),
+            _ => ((width + 1) / 2, (height + 1) / 2),
+        };
+        (width as usize, height as usize)
+    }
+
+    /// The bits of a sample, and whether they are the high bits of a word.
+    fn sample_bits(self) -> (u32, bool) {
+        match self {
+            VideoFormat::I420 | VideoFormat::NV12 | VideoFormat::YUY2 => (8, false),
+            VideoFormat::I420P10 => (10, false),
+            VideoFormat::I420P12 => (12, false),
+            VideoFormat::I420P16 => (16, false),
+            VideoFormat::P010 => (10, true),
+        }
+    }
+
+    /// The samples of a plane, scaled to 16 bits.
+    fn read_samples(self, data: &[u8]) -> Vec<u16> {
+        match self.sample_bits() {
+            (8, _) => data.iter().map(|&sample| (sample as u16) << 8).collect(),
+            (bits, high) => {
+                let shift = if high { 0 } else { 16 - bits };
+                data.chunks_exact(2).map(|word| u16::from_ne_bytes([word[0], word[1]]) << shift).collect()
+            }
+        }
+    }
+
+    /// A plane of 16 bit samples, dropping the bits it has no room for.
+    fn write_samples(self, samples: impl Iterator<Item = u16>) -> Vec<u8> {
+        match self.sample_bits() {
+            (8, _) => samples.map(|sample| (sample >> 8) as u8).collect(),
+            (bits, high) => {
+                let shift = 16 - bits;
+                samples
+                    .flat_map(|sample| {
+                        let sample = if high { sample >> shift << shift } else { sample >> shift };
+                        sample.to_ne_bytes().to_vec()
+                    })
+                    .collect()
+            }
+        }
+    }
+
+    /// Turns the planes of a file into those of a frame.
+    fn unpack(self, mut planes: Vec<Vec<u8>>, width: i32) -> Vec<Vec<u8>> {
+        if self == VideoFormat::YUY2 {
+            let row_len = ((width + 1) / 2 * 4) as usize;
+            let width = width as usize;
+            let texels = planes[0]
+                .chunks_exact(row_len)
+                .flat_map(|row| {
+                    (0..width).flat_map(move |x| {
+                        let pair = &row[x / 2 * 4..x / 2 * 4 + 4];
+                        vec![pair[3], pair[x % 2 * 2], pair[1], 255]
+                    })
+                })
+                .collect();
+            return vec![texels];
+        }
+        if cfg!(target_endian = "big") && self.sample_bits().0 > 8 {
+            for plane in &mut planes {
+                for word in plane.chunks_exact_mut(2) {
+                    word.swap(0, 1);
+                }
+            }
+        }
+        planes
+    }
+}
+
+/// The layout of a raw video file, which has no header to tell it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RawLayout {
+    pub format: VideoFormat,
+    pub width: i32,
+    pub height: i32,
+    pub frames_per_second: u32,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct VideoInfo {
+    pub width: i32,
+    pub height: i32,
+    pub format: VideoFormat,
+    /// Frames per second as a fraction, e.g. 30000/1001.
+    pub frame_rate: (u32, u32),
+    pub color_space: YuvColorSpace,
+    pub color_range: ColorRange,
+}
+
+impl VideoInfo {
+    /// What players assume when the file doesn't tell: Rec. 709 for HD
+    /// content and Rec. 601 below, limited range.
+    fn new(width: i32, height: i32, format: VideoFormat, frame_rate: (u32, u32)) -> Self {
+        VideoInfo {
+            width,
+            height,
+            format,
+            frame_rate,
+            color_space: if height > 576 { YuvColorSpace::Rec709 } else { YuvColorSpace::Rec601 },
+            color_range: ColorRange::Limited,
+        }
+    }
+
+    /// The frame rate rounded up to whole frames per second, so that a loop
+    /// running at it shows every frame.
+    pub fn frames_per_second(&self) -> u32 {
+        let (frames, seconds) = self.frame_rate;
+        (frames / seconds + (frames % seconds != 0) as u32).max(1)
+    }
+
+    pub fn frame_duration(&self) -> Duration {
+        let (frames, seconds) = self.frame_rate;
+        Duration::from_nanos(seconds as u64 * 1_000_000_000 / frames as u64)
+    }
+}
+
+#[derive(Debug)]
+pub enum VideoError {
+    Io(PathBuf, io::Error),
+    /// The file is not a Y4M file, or uses what isn't supported.
+    Header(PathBuf, String),
+    /// The file has no complete frame.
+    Empty(PathBuf),
+    /// The frames are empty or too large to read.
+    Size(PathBuf, i32, i32),
+}
+
+impl fmt::Display for VideoError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            VideoError::Io(path, err) => write!(f, "Failed to read video {:?}: {}", path, err),
+            VideoError::Header(path, err) => write!(f, "Invalid Y4M header in {:?}: {}", path, err),
+            VideoError::Empty(path) => write!(f, "No frames in video {:?}", path),
+            VideoError::Size(path, width, height) => {
+                write!(f, "Unsupported frame size {}x{} in video {:?}", width, height, path)
+            }
+        }
+    }
+}
+
+impl std::error::Error for VideoError {}
+
+/// The planes of a frame, as `VideoFormat::planes` lays them out.
+pub struct VideoFrame {
+    pub index: usize,
+    pub planes: Vec<Vec<u8>>,
+}
+
+impl VideoFrame {
+    /// The frame of `width` by `height` pixels in `from` laid out as `to`,
+    /// e.g. to compare how the formats show.
+    pub fn convert(&self, from: VideoFormat, to: VideoFormat, width: i32, height: i32) -> VideoFrame {
+        let (chroma_width, rows) = from.chroma_size(width, height);
+        let (_, new_rows) = to.chroma_size(width, height);
+        let (width, height) = (width as usize, height as usize);
+        let (y, u, v) = match from {
+            VideoFormat::I420 | VideoFormat::I420P10 | VideoFormat::I420P12 | VideoFormat::I420P16 => (
+                from.read_samples(&self.planes[0]),
+                from.read_samples(&self.planes[1]),
+                from.read_samples(&self.planes[2]),
+            ),
+            VideoFormat::NV12 | VideoFormat::P010 => {
+                let uv = from.read_samples(&self.planes[1]);
+                (
+                    from.read_samples(&self.planes[0]),
+                    uv.iter().copied().step_by(2).collect(),
+                    uv.iter().copied().skip(1).step_by(2).collect(),
+                )
+            }
+            VideoFormat::YUY2 => {
+                let texels = from.read_samples(&self.planes[0]);
+                let chroma = |channel| {
+                    texels.chunks_exact(width * 4).flat_map(move |row| row.chunks(8).map(move |pair| pair[channel]))
+                };
+                (
+                    texels.iter().copied().skip(1).step_by(4).collect(),
+                    chroma(2).collect(),
+                    chroma(0).collect(),
+                )
+            }
+        };
+        let u = resample_rows(&u, chroma_width, rows, new_rows);
+        let v = resample_rows(&v, chroma_width, rows, new_rows);
+
+        let planes = match to {
+            VideoFormat::I420 | VideoFormat::I420P10 | VideoFormat::I420P12 | VideoFormat::I420P16 => vec![
+                to.write_samples(y.into_iter()),
+                to.write_samples(u.into_iter()),
+                to.write_samples(v.into_iter()),
+            ],
+            VideoFormat::NV12 | VideoFormat::P010 => vec![
+                to.write_samples(y.into_iter()),
+                to.write_samples(u.into_iter().zip(v).flat_map(|(u, v)| vec![u, v])),
+            ],
+            VideoFormat::YUY2 => {
+                let texels = (0..width * height).flat_map(|index| {
+                    let chroma = index / width * chroma_width + index % width / 2;
+                    vec![v[chroma], y[index], u[chroma], u16::MAX]
+                });
+                vec![to.write_samples(texels)]
+            }
+        };
+        VideoFrame { index: self.index, planes }
+    }
+}
+
+/// Scales a plane of `rows` rows to `new_rows` rows, repeating rows to
+/// double them or averaging pairs to halve them.
+fn resample_rows(samples: &[u16], width: usize, rows: usize, new_rows: usize) -> Vec<u16> {
+    if rows == new_rows {
+        return samples.to_vec();
+    }
+    let row = |index: usize| &samples[index.min(rows - 1) * width..][..width];
+    (0..new_rows)
+        .flat_map(|index| {
+            if new_rows > rows {
+                row(index / 2).to_vec()
+            } else {
+                let (top, bottom) = (row(index * 2), row(index * 2 + 1));
+                top.iter().zip(bottom).map(|(&a, &b)| ((a as u32 + b as u32 + 1) >> 1) as u16).collect()
+            }
+        })
+        .collect()
+}
+
+/// Uncompressed video read frame by frame from a Y4M file or a raw dump of
+/// frames of any `VideoFormat`.
+pub struct VideoSource {
+    path: PathBuf,
+    file: BufReader<File>,
+    info: VideoInfo,
+    /// Where the data of each frame starts.
+    frame_offsets: Vec<u64>,
+}
+
+impl VideoSource {
+    /// Opens a Y4M file. Its colorspace tag only tells the chroma layout, so
+    /// the color matrix and range come from the `XCOLORMATRIX` (`BT601`,
+    /// `BT709` or `BT2020`) and `XCOLORRANGE` (`LIMITED` or `FULL`) options
+    /// when it has them.
+    pub fn open(path: &Path) -> Result<Self, VideoError> {
+        let io_error = |err| VideoError::Io(path.to_owned(), err);
+        let header_error = |message: String| VideoError::Header(path.to_owned(), message);
+
+        let mut file = BufReader::new(File::open(path).map_err(io_error)?);
+        let header = read_line(&mut file).map_err(io_error)?;
+        let mut params = header.split(' ');
+        if params.next() != Some("YUV4MPEG2") {
+            return Err(header_error("not a YUV4MPEG2 file".to_owned()));
+        }
+
+        let (mut width, mut height, mut frame_rate) = (0, 0, (25, 1));
+        let (mut format, mut color_space, mut color_range) = (VideoFormat::I420, None, None);
+        for param in params.filter(|param| !param.is_empty()) {
+            let (tag, value) = param.split_at(param.chars().next().map_or(0, char::len_utf8));
+            let invalid = || header_error(format!("invalid parameter '{}'", param));
+            match tag {
+                "W" => width = value.parse().map_err(|_| invalid())?,
+                "H" => height = value.parse().map_err(|_| invalid())?,
+                "F" => {
+                    let mut parts = value.splitn(2, ':').map(str::parse::<u32>);
+                    frame_rate = match (parts.next(), parts.next()) {
+                        (Some(Ok(frames)), Some(Ok(seconds))) if frames > 0 && seconds > 0 => (frames, seconds),
+                        _ => return Err(invalid()),
+                    };
+                }
+                "C" => {
+                    format = match value {
+                        "420" | "420jpeg" | "420paldv" | "420mpeg2" => VideoFormat::I420,
+                        "420p10" => VideoFormat::I420P10,
+                        "420p12" => VideoFormat::I420P12,
+                        "420p16" => VideoFormat::I420P16,
+                        _ => return Err(header_error(format!("unsupported colorspace '{}'", value))),
+                    };
+                }
+                "X" => {
+                    let mut option = value.splitn(2, '=');
+                    match (option.next(), option.next()) {
+                        (Some("COLORMATRIX"), Some(matrix)) => color_space = Some(parse_color_space(matrix).ok_or_else(invalid)?),
+                        (Some("COLORRANGE"), Some(range)) => color_range = Some(parse_color_range(range).ok_or_else(invalid)?),
+                        _ => {}
+                    }
+                }
+                // Interlacing, pixel aspect ratio and comments don't change
+                // how frames are shown here.
+                _ => {}
+            }
+        }
+        if width == 0 || height == 0 {
+            return Err(header_error("missing frame size".to_owned()));
+        }
+
+        let mut info = VideoInfo::new(width, height, format, frame_rate);
+        info.color_space = color_space.unwrap_or(info.color_space);
+        info.color_range = color_range.unwrap_or(info.color_range);
+
+        // Each frame has a header line of its own, which may carry
+        // parameters, so the frames are found up front for seeking.
+        let frame_size = frame_size(path, &info)? as u64;
+        let file_size = file.get_ref().metadata().map_err(io_error)?.len();
+        let mut frame_offsets = Vec::new();
+        loop {
+            let line = read_line(&mut file).map_err(io_error)?;
+            if !line.starts_with("FRAME") {
+                break;
+            }
+            let offset = file.stream_position().map_err(io_error)?;
+            if offset + frame_size > file_size {
+                break;
+            }
+            frame_offsets.push(offset);
+            file.seek(SeekFrom::Start(offset + frame_size)).map_err(io_error)?;
+        }
+
+        VideoSource::new(path, file, info, frame_offsets)
+    }
+
+    /// Opens a file of frames laid out as `layout` says, back to back.
+    pub fn open_raw(path: &Path, layout: RawLayout) -> Result<Self, VideoError> {
+        let io_error = |err| VideoError::Io(path.to_owned(), err);
+        let file = File::open(path).map_err(io_error)?;
+        let file_size = file.metadata().map_err(io_error)?.len();
+        let info = VideoInfo::new(layout.width, layout.height, layout.format, (layout.frames_per_second.max(1), 1));
+        let frame_size = frame_size(path, &info)? as u64;
+        let frame_offsets = (0..file_size / frame_size).map(|index| index * frame_size).collect();
+        VideoSource::new(path, BufReader::new(file), info, frame_offsets)
+    }
+
+    fn new(path: &Path, file: BufReader<File>, info: VideoInfo, frame_offsets: Vec<u64>) -> Result<Self, VideoError> {
+        if frame_offsets.is_empty() {
+            return Err(VideoError::Empty(path.to_owned()));
+        }
+        println!(
+            "Video {:?}: {}x{} {:?}, {} frames at {}/{} fps, {:?} {:?} range",
+            path, info.width, info.height, info.format, frame_offsets.len(),
+            info.frame_rate.0, info.frame_rate.1, info.color_space, info.color_range,
+        );
+        Ok(VideoSource { path: path.to_owned(), file, info, frame_offsets })
+    }
+
+    pub fn info(&self) -> &VideoInfo {
+        &self.info
+    }
+
+    pub fn frame_count(&self) -> usize {
+        self.frame_offsets.len()
+    }
+
+    pub fn duration(&self) -> Duration {
+        self.info.frame_duration() * self.frame_count() as u32
+    }
+
+    /// The frame shown `time` after the start, the last one after the end.
+    pub fn frame_at(&self, time: Duration) -> usize {
+        let (frames, seconds) = self.info.frame_rate;
+        let index = time.as_nanos() * frames as u128 / (seconds as u128 * 1_000_000_000);
+        (index as usize).min(self.frame_count() - 1)
+    }
+
+    /// When the frame `index` starts.
+    pub fn frame_time(&self, index: usize) -> Duration {
+        self.info.frame_duration() * index as u32
+    }
+
+    pub fn read_frame(&mut self, index: usize) -> Result<VideoFrame, VideoError> {
+        let path = &self.path;
+        let io_error = |err| VideoError::Io(path.clone(), err);
+        let offset = self.frame_offsets[index.min(self.frame_offsets.len() - 1)];
+        self.file.seek(SeekFrom::Start(offset)).map_err(io_error)?;
+        let mut planes = Vec::new();
+        for plane in self.info.format.stored_planes(self.info.width, self.info.height) {
+            // `open` checked that frames fit in memory.
+            let mut data = vec![0; plane.data_len().unwrap()];
+            self.file.read_exact(&mut data).map_err(io_error)?;
+            planes.push(data);
+        }
+        Ok(VideoFrame { index, planes: self.info.format.unpack(planes, self.info.width) })
+    }
+}
+
+/// The bytes of a frame as files store it. Fails for empty frames and for
+/// frames too large to read.
+fn frame_size(path: &Path, info: &VideoInfo) -> Result<usize, VideoError> {
+    let size_error = || VideoError::Size(path.to_owned(), info.width, info.height);
+    let dimensions = 1..=MAX_FRAME_DIMENSION;
+    if !dimensions.contains(&info.width) || !dimensions.contains(&info.height) {
+        return Err(size_error());
+    }
+    info.format
+        .stored_planes(info.width, info.height)
+        .iter()
+        .try_fold(0usize, |size, plane| size.checked_add(plane.data_len()?))
+        .ok_or_else(size_error)
+}
+
+/// Reads a header line, without the line feed.
+fn read_line(file: &mut BufReader<File>) -> io::Result<String> {
+    let mut line = Vec::new();
+    file.by_ref().take(4096).read_until(b'\n', &mut line)?;
+    if line.last() == Some(&b'\n') {
+        line.pop();
+    }
+    Ok(String::from_utf8_lossy(&line).into_owned())
+}
+
+/// Parses `BT601`, `BT709` or `BT2020`, with or without the `BT`.
+pub fn parse_color_space(name: &str) -> Option<YuvColorSpace> {
+    match name.to_ascii_uppercase().trim_start_matches("BT").trim_start_matches("REC") {
+        "601" => Some(YuvColorSpace::Rec601),
+        "709" => Some(YuvColorSpace::Rec709),
+        "2020" => Some(YuvColorSpace::Rec2020),
+        _ => None,
+    }
+}
+
+/// Parses `LIMITED` or `FULL`, in any case.
+pub fn parse_color_range(name: &str) -> Option<ColorRange> {
+    match name.to_ascii_uppercase().as_str() {
+        "LIMITED" => Some(ColorRange::Limited),
+        "FULL" => Some(ColorRange::Full),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, fs, ops::Deref, process};
+
+    /// A file in the temporary directory, deleted on drop.
+    struct TempFile(PathBuf);
+
+    impl Deref for TempFile {
+        type Target = Path;
+
+        fn deref(&self) -> &Path {
+            &self.0
+        }
+    }
+
+    impl Drop for TempFile {
+        fn drop(&mut self) {
+            let _ = fs::remove_file(&self.0);
+        }
+    }
+
+    /// Writes a video into the temporary directory, named after the test.
+    /// It has to outlive the sources reading it.
+    fn write_video(name: &str, header: &str, frames: &[&[u8]]) -> TempFile {
+        let path = env::temp_dir().join(format!("demo-video-{}-{}", process::id(), name));
+        let mut bytes = header.as_bytes().to_vec();
+        for frame in frames {
+            if !header.is_empty() {
+                bytes.extend_from_slice(b"FRAME\n");
+            }
+            bytes.extend_from_slice(frame);
+        }
+        fs::write(&path, bytes).unwrap();
+        TempFile(path)
+    }
+
+    /// Opens a video of one 2x2 frame, returns its info and frame count.
+    fn open_header(name: &str, header: &str) -> Result<(VideoInfo, usize), VideoError> {
+        let path = write_video(name, &format!("{}\n", header), &[&[0; 6]]);
+        VideoSource::open(&path).map(|video| (*video.info(), video.frame_count()))
+    }
+
+    #[test]
+    fn parses_header_tags() {
+        let header = "YUV4MPEG2 W4 H2 F30000:1001 C420p10 Ip A1:1 XCOLORMATRIX=BT2020 XCOLORRANGE=FULL XYSCSS=420P10\n";
+        // The last frame is cut short, so it isn't counted.
+        let path = write_video("tags", header, &[&[0; 24], &[0; 24], &[0; 12]]);
+        let video = VideoSource::open(&path).unwrap();
+        let info = video.info();
+        assert_eq!((info.width, info.height), (4, 2));
+        assert_eq!(info.format, VideoFormat::I420P10);
+        assert_eq!(info.frame_rate, (30000, 1001));
+        assert_eq!(info.frames_per_second(), 30);
+        assert_eq!(info.color_space, YuvColorSpace::Rec2020);
+        assert_eq!(info.color_range, ColorRange::Full);
+        assert_eq!(video.frame_count(), 2);
+    }
+
+    #[test]
+    fn defaults_missing_tags() {
+        let (info, _) = open_header("defaults", "YUV4MPEG2 W2 H2").unwrap();
+        assert_eq!(info.format, VideoFormat::I420);
+        assert_eq!(info.frame_rate, (25, 1));
+        assert_eq!(info.color_space, YuvColorSpace::Rec601);
+        assert_eq!(info.color_range, ColorRange::Limited);
+
+        // Tags starting with other characters are skipped.
+        assert_eq!(open_header("unicode", "YUV4MPEG2 W2 H2 éx").unwrap().1, 1);
+    }
+
+    #[test]
+    fn rejects_invalid_headers() {
+        let header_error = |name, header| match open_header(name, header) {
+            Err(VideoError::Header(_, message)) => message,
+            _ => panic!("{:?} should have an invalid header", header),
+        };
+        assert_eq!(header_error("magic", "YUV4MPEG W2 H2"), "not a YUV4MPEG2 file");
+        assert_eq!(header_error("no-size", "YUV4MPEG2 W2"), "missing frame size");
+        assert_eq!(header_error("width", "YUV4MPEG2 Wé H2"), "invalid parameter 'Wé'");
+        assert_eq!(header_error("rate", "YUV4MPEG2 W2 H2 F30:0"), "invalid parameter 'F30:0'");
+        assert_eq!(header_error("colorspace", "YUV4MPEG2 W2 H2 C444"), "unsupported colorspace '444'");
+        assert_eq!(
+            header_error("matrix", "YUV4MPEG2 W2 H2 XCOLORMATRIX=BT999"),
+            "invalid parameter 'XCOLORMATRIX=BT999'"
+        );
+        assert_eq!(
+            header_error("range", "YUV4MPEG2 W2 H2 XCOLORRANGE=PARTIAL"),
+            "invalid parameter 'XCOLORRANGE=PARTIAL'"
+        );
+
+        let path = write_video("empty", "YUV4MPEG2 W2 H2\n", &[]);
+        assert!(matches!(VideoSource::open(&path), Err(VideoError::Empty(_))));
+    }
+
+    #[test]
+    fn rejects_unsupported_sizes() {
+        assert!(matches!(open_header("negative", "YUV4MPEG2 W-2 H2"), Err(VideoError::Size(_, -2, 2))));
+        assert!(matches!(open_header("wide", "YUV4MPEG2 W70000 H2"), Err(VideoError::Size(_, 70000, 2))));
+
+        let path = write_video("huge", "", &[&[0; 6]]);
+        let layout = RawLayout { format: VideoFormat::P010, width: i32::MAX, height: i32::MAX, frames_per_second: 30 };
+        assert!(matches!(VideoSource::open_raw(&path, layout), Err(VideoError::Size(..))));
+
+        let plane = PlaneLayout { width: i32::MAX, height: i32::MAX, format: ImageFormat::RGBAF32 };
+        assert_eq!(plane.data_len(), None);
+    }
+
+    #[test]
+    fn reads_raw_frames() {
+        let path = write_video("raw", "", &[&[1; 6], &[2; 6], &[3; 6], &[4; 3]]);
+        let layout = RawLayout { format: VideoFormat::I420, width: 2, height: 2, frames_per_second: 10 };
+        let mut video = VideoSource::open_raw(&path, layout).unwrap();
+        assert_eq!(video.frame_count(), 3);
+        assert_eq!(video.duration(), Duration::from_millis(300));
+
+        let frame = video.read_frame(1).unwrap();
+        assert_eq!(frame.planes, [vec![2; 4], vec![2], vec![2]]);
+        // Frames past the end read the last one.
+        assert_eq!(video.read_frame(5).unwrap().planes[0], [3; 4]);
+    }
+
+    #[test]
+    fn finds_the_frame_at_a_time() {
+        let layout = RawLayout { format: VideoFormat::I420, width: 2, height: 2, frames_per_second: 10 };
+        let path = write_video("times", "", &[&[0u8; 6][..]; 3]);
+        let video = VideoSource::open_raw(&path, layout).unwrap();
+        assert_eq!(video.frame_at(Duration::from_millis(0)), 0);
+        assert_eq!(video.frame_at(Duration::from_millis(99)), 0);
+        assert_eq!(video.frame_at(Duration::from_millis(100)), 1);
+        assert_eq!(video.frame_at(Duration::from_secs(10)), 2);
+        assert_eq!(video.frame_time(2), Duration::from_millis(200));
+
+        let header = "YUV4MPEG2 W2 H2 F30000:1001\n";
+        let path = write_video("ntsc", header, &[&[0u8; 6][..]; 3]);
+        let video = VideoSource::open(&path).unwrap();
+        assert_eq!(video.frame_at(Duration::from_micros(33_366)), 0);
+        assert_eq!(video.frame_at(Duration::from_micros(33_367)), 1);
+    }
+
+    #[test]
+    fn resamples_rows() {
+        let samples = [0, 2, 4, 6];
+        assert_eq!(resample_rows(&samples, 2, 2, 2), samples);
+        assert_eq!(resample_rows(&samples, 2, 2, 1), [2, 4]);
+        assert_eq!(resample_rows(&samples, 2, 2, 4), [0, 2, 0, 2, 4, 6, 4, 6]);
+        // An odd last row is averaged with itself.
+        assert_eq!(resample_rows(&[0, 10, 20], 1, 3, 2), [5, 20]);
+    }
+
+    #[test]
+    fn conversions_round_trip() {
+        // 4x2 pixels, one row of 2x1 chroma for I420.
+        let planes = vec![vec![16, 50, 100, 235, 20, 60, 110, 230], vec![64, 192], vec![128, 240]];
+        let frame = VideoFrame { index: 0, planes };
+        for &format in &VideoFormat::ALL {
+            let converted = frame.convert(VideoFormat::I420, format, 4, 2);
+            let expected_len: Vec<_> = format.planes(4, 2).iter().map(|plane| plane.data_len().unwrap()).collect();
+            assert_eq!(converted.planes.iter().map(Vec::len).collect::<Vec<_>>(), expected_len, "{:?}", format);
+            assert_eq!(converted.convert(format, VideoFormat::I420, 4, 2).planes, frame.planes, "{:?}", format);
+        }
+
+        let nv12 = frame.convert(VideoFormat::I420, VideoFormat::NV12, 4, 2);
+        assert_eq!(nv12.planes[1], [64, 128, 192, 240]);
+        let i420p10 = frame.convert(VideoFormat::I420, VideoFormat::I420P10, 4, 2);
+        assert_eq!(i420p10.planes[1][..2], (64u16 << 2).to_ne_bytes());
+    }
+
+    #[test]
+    fn unpacks_yuy2_to_a_texel_per_pixel() {
+        // Two rows of 3 pixels, each a Y0 U Y1 V group and a half used one.
+        let rows = vec![1, 2, 3, 4, 5, 6, 7, 8, 11, 12, 13, 14, 15, 16, 17, 18];
+        let planes = VideoFormat::YUY2.unpack(vec![rows.clone()], 3);
+        assert_eq!(
+            planes,
+            [vec![4, 1, 2, 255, 4, 3, 2, 255, 8, 5, 6, 255, 14, 11, 12, 255, 14, 13, 12, 255, 18, 15, 16, 255]]
+        );
+
+        let layout = RawLayout { format: VideoFormat::YUY2, width: 3, height: 2, frames_per_second: 30 };
+        let path = write_video("yuy2", "", &[&rows]);
+        let mut video = VideoSource::open_raw(&path, layout).unwrap();
+        assert_eq!(video.read_frame(0).unwrap().planes, planes);
+    }
+}
@@ -0,0 +1,85 @@
+use std::{
+    fs,
+    path::PathBuf,
+    sync::{Arc, Mutex, atomic::{AtomicBool, Ordering}},
+    thread,
+    time::{Duration, SystemTime}
+};
+
+/// How often the watched files are checked for modifications.
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Polls the modification times of a set of files on a background thread.
+pub struct FileWatcher {
+    paths: Arc<Mutex<Vec<PathBuf>>>,
+    changed: Arc<AtomicBool>,
+    stopped: Arc<AtomicBool>,
+}
+
+impl FileWatcher {
+    /// Starts watching `paths`, `on_change` is called from the watcher
+    /// thread whenever one of them is modified, created or removed.
+    pub fn new<F>(paths: Vec<PathBuf>, on_change: F) -> FileWatcher
+    where
+        F: Fn() + Send + 'static,
+    {
+        let paths = Arc::new(Mutex::new(paths));
+        let changed = Arc::new(AtomicBool::new(false));
+        let stopped = Arc::new(AtomicBool::new(false));
+
+        let watcher = FileWatcher {
+            paths: paths.clone(),
+            changed: changed.clone(),
+            stopped: stopped.clone(),
+        };
+
+        thread::Builder::new()
+            .name("FileWatcher".to_owned())
+            .spawn(move || {
+                let mut last_seen = modification_times(&paths.lock().unwrap());
+                while !stopped.load(Ordering::SeqCst) {
+                    thread::sleep(POLL_INTERVAL);
+                    let current = modification_times(&paths.lock().unwrap());
+                    // A new set of paths only becomes the baseline.
+                    let same_paths = current
+                        .iter()
+                        .map(|(path, _)| path)
+                        .eq(last_seen.iter().map(|(path, _)| path));
+                    if same_paths && current != last_seen {
+                        changed.store(true, Ordering::SeqCst);
+                        on_change();
+                    }
+                    last_seen = current;
+                }
+            })
+            .expect("Failed to spawn the file watcher thread");
+
+        watcher
+    }
+
+    /// Replaces the watched files, e.g. after a reload referenced new ones.
+    pub fn set_paths(&self, paths: Vec<PathBuf>) {
+        *self.paths.lock().unwrap() = paths;
+    }
+
+    /// Returns whether a file changed since the last call.
+    pub fn take_changed(&self) -> bool {
+        self.changed.swap(false, Ordering::SeqCst)
+    }
+}
+
+impl Drop for FileWatcher {
+    fn drop(&mut self) {
+        self.stopped.store(true, Ordering::SeqCst);
+    }
+}
+
+fn modification_times(paths: &[PathBuf]) -> Vec<(PathBuf, Option<SystemTime>)> {
+    paths
+        .iter()
+        .map(|path| {
+            let modified = fs::metadata(path).and_then(|metadata| metadata.modified()).ok();
+            (path.clone(), modified)
+        })
+        .collect()
+}
@@ -5,7 +5,8 @@
  #![allow(dead_code)]
  
 use euclid::default::Size2D;
-use std::{cell::RefCell, ffi::c_void, rc::Rc};
+use gleam::gl;
+use std::{cell::RefCell, ffi::c_void, fmt, rc::Rc};
 use surfman::{
     Adapter, Connection, Context, ContextAttributeFlags, ContextAttributes,
     Device, Error, GLApi, GLVersion, NativeContext, NativeDevice, NativeWidget,
@@ -13,6 +14,51 @@ use surfman::{
 };
 use surfman_chains::SwapChain;
 
+/// The kind of adapter to render with.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AdapterKind {
+    Hardware,
+    /// The integrated GPU on systems with several.
+    LowPower,
+    /// A CPU rasterizer, for machines without a usable GPU.
+    Software,
+}
+
+impl AdapterKind {
+    pub fn create_adapter(self, connection: &Connection) -> Result<Adapter, Error> {
+        match self {
+            AdapterKind::Hardware => connection.create_hardware_adapter(),
+            AdapterKind::LowPower => connection.create_low_power_adapter(),
+            AdapterKind::Software => connection.create_software_adapter(),
+        }
+    }
+}
+
+/// Why `WebrenderSurfmanBuilder::build` failed.
+#[derive(Debug)]
+pub enum BuildError {
+    /// No connection to the display server.
+    Connection(Error),
+    /// No adapter of the requested kind, nor a software one.
+    Adapter(Error),
+    /// The surface type, e.g. the native widget of a window, could not be
+    /// created.
+    SurfaceType(Error),
+    /// Creating the device, context or surface on the adapter.
+    Context(Error),
+}
+
+impl fmt::Display for BuildError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BuildError::Connection(err) => write!(f, "Failed to create surfman connection: {:?}", err),
+            BuildError::Adapter(err) => write!(f, "Failed to create surfman adapter: {:?}", err),
+            BuildError::SurfaceType(err) => write!(f, "Failed to create native widget: {:?}", err),
+            BuildError::Context(err) => write!(f, "Failed to set up GL context: {:?}", err),
+        }
+    }
+}
+
 /// A bridge between webrender and surfman
 // TODO: move this into a different crate so that script doesn't depend on surfman
 #[derive(Clone)]
@@ -36,23 +82,103 @@ impl Drop for WebrenderSurfmanData {
     }
 }
 
-impl WebrenderSurfman {
-    pub fn create(
+/// The adapter and context a `WebrenderSurfman` is created with.
+#[derive(Clone, Debug)]
+pub struct WebrenderSurfmanBuilder {
+    adapter: AdapterKind,
+    flags: ContextAttributeFlags,
+    gl_versions: Vec<GLVersion>,
+    gles_versions: Vec<GLVersion>,
+}
+
+impl Default for WebrenderSurfmanBuilder {
+    fn default() -> Self {
+        WebrenderSurfmanBuilder {
+            adapter: AdapterKind::Hardware,
+            flags: ContextAttributeFlags::ALPHA |
+                ContextAttributeFlags::DEPTH |
+                ContextAttributeFlags::STENCIL,
+            gl_versions: vec![GLVersion { major: 3, minor: 2 }],
+            gles_versions: vec![GLVersion { major: 3, minor: 0 }],
+        }
+    }
+}
+
+impl WebrenderSurfmanBuilder {
+    /// The adapter tried first by `build`.
+    pub fn adapter(mut self, adapter: AdapterKind) -> Self {
+        self.adapter = adapter;
+        self
+    }
+
+    pub fn flags(mut self, flags: ContextAttributeFlags) -> Self {
+        self.flags = flags;
+        self
+    }
+
+    /// Desktop GL versions to request, in order of preference.
+    pub fn gl_versions(mut self, versions: &[GLVersion]) -> Self {
+        self.gl_versions = versions.to_vec();
+        self
+    }
+
+    /// GLES versions to request, in order of preference.
+    pub fn gles_versions(mut self, versions: &[GLVersion]) -> Self {
+        self.gles_versions = versions.to_vec();
+        self
+    }
+
+    /// Creates the bridge on the configured adapter, retrying with the
+    /// software adapter if the adapter or its context can't be created. The
+    /// surface type is requested again for the second attempt, a failure to
+    /// create it is returned right away.
+    pub fn build<F>(&self, connection: &Connection, mut surface_type: F) -> Result<WebrenderSurfman, BuildError>
+    where
+        F: FnMut() -> Result<SurfaceType<NativeWidget>, Error>,
+    {
+        let mut create = |kind: AdapterKind| -> Result<WebrenderSurfman, BuildError> {
+            let adapter = kind.create_adapter(connection).map_err(BuildError::Adapter)?;
+            let surface_type = surface_type().map_err(BuildError::SurfaceType)?;
+            self.build_with_adapter(connection, &adapter, surface_type)
+                .map_err(BuildError::Context)
+        };
+
+        match create(self.adapter) {
+            Err(err @ BuildError::Adapter(_)) | Err(err @ BuildError::Context(_))
+                if self.adapter != AdapterKind::Software =>
+            {
+                println!("No {:?} adapter ({}), falling back to software", self.adapter, err);
+                create(AdapterKind::Software)
+            }
+            result => result,
+        }
+    }
+
+    /// Creates the bridge on `adapter`, with the first of the requested GL
+    /// versions the device supports.
+    pub fn build_with_adapter(
+        &self,
         connection: &Connection,
         adapter: &Adapter,
         surface_type: SurfaceType<NativeWidget>,
-    ) -> Result<Self, Error> {
+    ) -> Result<WebrenderSurfman, Error> {
         let mut device = connection.create_device(&adapter)?;
-        let flags = ContextAttributeFlags::ALPHA |
-            ContextAttributeFlags::DEPTH |
-            ContextAttributeFlags::STENCIL;
-        let version = match connection.gl_api() {
-            GLApi::GLES => GLVersion { major: 3, minor: 0 },
-            GLApi::GL => GLVersion { major: 3, minor: 2 },
+        let versions = match connection.gl_api() {
+            GLApi::GLES => &self.gles_versions,
+            GLApi::GL => &self.gl_versions,
         };
-        let context_attributes = ContextAttributes { flags, version };
-        let context_descriptor = device.create_context_descriptor(&context_attributes)?;
-        let mut context = device.create_context(&context_descriptor, None)?;
+        let mut context = Err(Error::UnsupportedGLVersion);
+        for &version in versions {
+            let context_attributes = ContextAttributes { flags: self.flags, version };
+            context = device
+                .create_context_descriptor(&context_attributes)
+                .and_then(|context_descriptor| device.create_context(&context_descriptor, None));
+            match context {
+                Ok(_) => break,
+                Err(ref err) => println!("Failed to create a {:?} context: {:?}", context_attributes, err),
+            }
+        }
+        let mut context = context?;
         let surface_access = SurfaceAccess::GPUOnly;
         let headless = match surface_type {
             SurfaceType::Widget { .. } => false,
@@ -83,6 +209,27 @@ impl WebrenderSurfman {
         };
         Ok(WebrenderSurfman(Rc::new(data)))
     }
+}
+
+impl WebrenderSurfman {
+    pub fn create(
+        connection: &Connection,
+        adapter: &Adapter,
+        surface_type: SurfaceType<NativeWidget>,
+    ) -> Result<Self, Error> {
+        WebrenderSurfmanBuilder::default().build_with_adapter(connection, adapter, surface_type)
+    }
+
+    /// Wraps the bridge to move it to another thread, e.g. a render thread.
+    /// Fails if the bridge has been cloned. See `SyncWebrenderSurfman` for
+    /// the platforms this is sound on.
+    #[cfg(not(target_os = "windows"))]
+    pub fn into_sync(mut self) -> Result<SyncWebrenderSurfman, Self> {
+        if Rc::get_mut(&mut self.0).is_none() {
+            return Err(self);
+        }
+        Ok(SyncWebrenderSurfman(self))
+    }
 
     pub fn create_surface_texture(
         &self,
@@ -118,7 +265,9 @@ impl WebrenderSurfman {
         if let Some(swap_chain) = self.0.swap_chain.as_ref() {
             return swap_chain.resize(device, context, size);
         }
-        let mut surface = device.unbind_surface_from_context(context)?.unwrap();
+        let mut surface = device
+            .unbind_surface_from_context(context)?
+            .ok_or(Error::Failed)?;
         device.resize_surface(context, &mut surface, size)?;
         device
             .bind_surface_to_context(context, surface)
@@ -134,7 +283,9 @@ impl WebrenderSurfman {
         if let Some(ref swap_chain) = self.0.swap_chain {
             return swap_chain.swap_buffers(device, context);
         }
-        let mut surface = device.unbind_surface_from_context(context)?.unwrap();
+        let mut surface = device
+            .unbind_surface_from_context(context)?
+            .ok_or(Error::Failed)?;
         device.present_surface(context, &mut surface)?;
         device
             .bind_surface_to_context(context, surface)
@@ -165,6 +316,7 @@ impl WebrenderSurfman {
         device.native_device()
     }
 
+    /// The flags and GL version of the context that was obtained.
     pub fn context_attributes(&self) -> ContextAttributes {
         let ref device = self.0.device.borrow();
         let ref context = self.0.context.borrow();
@@ -193,4 +345,47 @@ impl WebrenderSurfman {
         let ref context = self.0.context.borrow();
         device.get_proc_address(context, name)
     }
+
+    /// Loads the GL or GLES bindings of the context.
+    pub fn gl_bindings(&self) -> Rc<dyn gl::Gl> {
+        match self.connection().gl_api() {
+            GLApi::GL => unsafe { gl::GlFns::load_with(|s| self.get_proc_address(s)) },
+            GLApi::GLES => unsafe { gl::GlesFns::load_with(|s| self.get_proc_address(s)) },
+        }
+    }
+
+    /// The texture target of the textures surfaces are imported as, e.g.
+    /// `TEXTURE_RECTANGLE` on macOS.
+    pub fn surface_gl_texture_target(&self) -> u32 {
+        let ref device = self.0.device.borrow();
+        device.surface_gl_texture_target()
+    }
+}
+
+/// A `WebrenderSurfman` on its way to another thread, unwrapped there with
+/// `into_local`. It can't be used or cloned in between.
+///
+/// Moving the device and context is sound with the surfman backends that
+/// only hold handles which may be made current on any thread, as long as
+/// they are current on one thread at a time: EGL on Linux (Wayland and the
+/// surfaceless and X11 EGL backends) and Android, and CGL on macOS. It is
+/// not on Windows: the WGL backend's device owns a hidden window, and ANGLE's
+/// a D3D11 device context, both tied to the thread that created them, so
+/// the wrapper doesn't exist there.
+#[cfg(not(target_os = "windows"))]
+pub struct SyncWebrenderSurfman(WebrenderSurfman);
+
+// SAFETY: `into_sync` checked that nothing else holds the `Rc`, and the
+// wrapper gives no access to it until `into_local`, so the device and
+// context are only ever used by one thread. See above for the platforms
+// this holds on.
+#[cfg(not(target_os = "windows"))]
+unsafe impl Send for SyncWebrenderSurfman {}
+
+#[cfg(not(target_os = "windows"))]
+impl SyncWebrenderSurfman {
+    /// Turns the wrapper back into the bridge, on the thread it was moved to.
+    pub fn into_local(self) -> WebrenderSurfman {
+        self.0
+    }
 }
@@ -1,4 +1,7 @@
-use crate::webrender_surfman::WebrenderSurfman;
+use crate::{
+    error::Error,
+    webrender_surfman::{BuildError, WebrenderSurfman, WebrenderSurfmanBuilder}
+};
 use euclid::{Point2D, Scale, Size2D};
 use std::{cell::{Cell, RefCell}, rc::Rc};
 use surfman::{Connection, SurfaceType};
@@ -8,7 +11,7 @@ use webrender::api::{
 };
 use winit::{
     dpi::{LogicalPosition, LogicalSize, PhysicalSize},
-    EventsLoop, WindowBuilder,
+    EventsLoop, WindowBuilder, WindowId,
 };
 
 #[derive(Clone, Copy, Debug)]
@@ -39,26 +42,36 @@ impl EmbedderCoordinates {
     }
 }
 
+/// What the compositor needs to know about the surface it renders into.
+pub trait WindowMethods {
+    /// Creates the GL context rendering into the window. The window doesn't
+    /// keep a reference to it, so it can be moved to a render thread.
+    fn create_webrender_surfman(&self, surfman: &WebrenderSurfmanBuilder) -> Result<WebrenderSurfman, BuildError>;
+    /// Fails if the native window is gone.
+    fn get_coordinates(&self) -> Result<EmbedderCoordinates, Error>;
+}
+
 pub struct Window {
     winit_window: winit::Window,
-    webrender_surfman: WebrenderSurfman,
     screen_size: Size2D<u32, DeviceIndependentPixel>,
     pub inner_size: Cell<Size2D<u32, DeviceIndependentPixel>>,
 }
 
 impl Window {
-    pub fn new(name: &'static str, size: LogicalSize, events_loop: Rc<RefCell<EventsLoop>>) -> Self {
+    pub fn new(
+        name: &str,
+        size: LogicalSize,
+        events_loop: Rc<RefCell<EventsLoop>>,
+    ) -> Result<Self, Error> {
         let window_builder = WindowBuilder::new()
             .with_title(name)
             // .with_decorations(true)
-            .with_resizable(false)
+            .with_resizable(true)
             .with_visibility(true)
             .with_dimensions(size)
             .with_multitouch();
 
-        let winit_window = window_builder
-            .build(&events_loop.borrow())
-            .expect("Faild to create window");
+        let winit_window = window_builder.build(&events_loop.borrow())?;
 
         let primary_monitor = events_loop.borrow().get_primary_monitor();
 
@@ -69,50 +82,51 @@ impl Window {
 
         let screen_size = Size2D::new(screen_width as u32, screen_height as u32);
 
-        let LogicalSize { width, height } = winit_window
-            .get_inner_size()
-            .expect("Failed to get window inner size.");
+        let LogicalSize { width, height } = winit_window.get_inner_size().unwrap_or(size);
         let inner_size = Size2D::new(width as u32, height as u32);
 
         winit_window.show();
 
-        // initialize surfman
-        let connection =
-            Connection::from_winit_window(&winit_window).expect("Faild to create connection");
-        let adapter = connection
-            .create_adapter()
-            .expect("Failed to create adapter");
-        let native_widget = connection
-            .create_native_widget_from_winit_window(&winit_window)
-            .expect("Failed to create native widget");
-        let surface_type = SurfaceType::Widget { native_widget };
-        let webrender_surfman = WebrenderSurfman::create(&connection, &adapter, surface_type)
-            .expect("Failed to create webrender surfman");
-
         println!("Created window {:?}", winit_window.id());
 
-        Window {
+        Ok(Window {
             winit_window,
-            webrender_surfman,
             screen_size,
             inner_size: Cell::new(inner_size),
-        }
+        })
+    }
+
+    pub fn id(&self) -> WindowId {
+        self.winit_window.id()
+    }
+
+    /// Records the new inner size of the window after a `WindowEvent::Resized`.
+    pub fn set_inner_size(&self, size: LogicalSize) {
+        self.inner_size.set(Size2D::new(size.width as u32, size.height as u32));
     }
 
     fn device_hidpi_factor(&self) -> Scale<f32, DeviceIndependentPixel, DevicePixel> {
         Scale::new(self.winit_window.get_hidpi_factor() as f32)
     }
 
-    pub fn webrender_surfman(&self) -> WebrenderSurfman {
-        self.webrender_surfman.clone()
+}
+
+impl WindowMethods for Window {
+    fn create_webrender_surfman(&self, surfman: &WebrenderSurfmanBuilder) -> Result<WebrenderSurfman, BuildError> {
+        let connection =
+            Connection::from_winit_window(&self.winit_window).map_err(BuildError::Connection)?;
+        surfman.build(&connection, || {
+            let native_widget = connection.create_native_widget_from_winit_window(&self.winit_window)?;
+            Ok(SurfaceType::Widget { native_widget })
+        })
     }
 
-    pub fn get_coordinates(&self) -> EmbedderCoordinates {
+    fn get_coordinates(&self) -> Result<EmbedderCoordinates, Error> {
         let dpr = self.device_hidpi_factor();
         let LogicalSize { width, height } = self
             .winit_window
             .get_outer_size()
-            .expect("Failed to get window outer size.");
+            .ok_or(Error::WindowClosed)?;
         let LogicalPosition { x, y } = self
             .winit_window
             .get_position()
@@ -124,19 +138,19 @@ impl Window {
         let LogicalSize { width, height } = self
             .winit_window
             .get_inner_size()
-            .expect("Failed to get window inner size.");
+            .ok_or(Error::WindowClosed)?;
         let inner_size = (Size2D::new(width as f32, height as f32) * dpr).to_i32();
         let viewport = DeviceIntRect::new(Point2D::zero(), inner_size);
         let framebuffer = DeviceIntSize::from_untyped(viewport.size.to_untyped());
         let hidpi_factor = self.device_hidpi_factor();
         let layout = framebuffer.to_f32() / Scale::new(hidpi_factor.get());
-        EmbedderCoordinates {
+        Ok(EmbedderCoordinates {
             viewport,
             framebuffer,
             window: (win_size, win_origin),
             screen,
             hidpi_factor,
             layout
-        }
+        })
     }
 }
@@ -0,0 +1,28 @@
+//! Renders the examples with references in `res/reftests` offscreen and
+//! compares them, see `demo reftest --help`. Rebless them with
+//! `cargo run -- reftest --bless` after intended changes.
+
+use std::{path::Path, process::Command};
+
+#[test]
+fn examples_match_their_references() {
+    let reference_dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("res/reftests");
+    if !reference_dir.is_dir() {
+        println!(
+            "Skipping the reftests, {:?} is missing. Bless the references with `cargo run -- reftest --bless`.",
+            reference_dir,
+        );
+        return;
+    }
+    let output = Command::new(env!("CARGO_BIN_EXE_demo"))
+        .arg("reftest")
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .output()
+        .expect("Failed to run the demo binary");
+    assert!(
+        output.status.success(),
+        "{}{}",
+        String::from_utf8_lossy(&output.stdout),
+        String::from_utf8_lossy(&output.stderr),
+    );
+}